@@ -0,0 +1,611 @@
+use crate::config_io::{write_gitcmd_conf, GitCmdAlias, GitCmdConfig};
+use crate::input_handler::InputHandler;
+use crate::input_parser::{
+    display_list, parse_command_list, requote_in_double_quotes, split_words, strip_redirects,
+    ListItem, ListNode,
+};
+use std::io::{self, Write};
+
+pub fn find_alias<'a>(name: &str, aliases: &'a [GitCmdAlias]) -> Option<&'a GitCmdAlias> {
+    aliases.iter().find(|alias| alias.identifier == name)
+}
+
+// Nested aliases deeper than this are almost certainly a mistake
+const MAX_ALIAS_DEPTH: usize = 16;
+
+// Parses the line and replaces the first word of every command with the
+// matching alias body. Compound aliases (`gac=add -A && commit`) become a
+// group, and the words that followed the alias name are bound to its
+// placeholders. Alias bodies are expanded again until only plain commands remain.
+pub fn expand_aliases(line: &str, aliases: &[GitCmdAlias]) -> Result<Vec<ListItem>, String> {
+    expand_line(line, aliases, &mut Vec::new(), &mut Vec::new())
+}
+
+// Same as `expand_aliases`, but also returns one line per alias that was
+// expanded, indented by nesting depth, so `which` can show the whole chain.
+pub fn trace_aliases(
+    line: &str,
+    aliases: &[GitCmdAlias],
+) -> Result<(Vec<ListItem>, Vec<String>), String> {
+    let mut trace = Vec::new();
+    let expanded = expand_line(line, aliases, &mut Vec::new(), &mut trace)?;
+    Ok((expanded, trace))
+}
+
+fn expand_line(
+    line: &str,
+    aliases: &[GitCmdAlias],
+    chain: &mut Vec<String>,
+    trace: &mut Vec<String>,
+) -> Result<Vec<ListItem>, String> {
+    let items = parse_command_list(line)?;
+    expand_items(items, aliases, chain, trace)
+}
+
+fn expand_items(
+    items: Vec<ListItem>,
+    aliases: &[GitCmdAlias],
+    chain: &mut Vec<String>,
+    trace: &mut Vec<String>,
+) -> Result<Vec<ListItem>, String> {
+    items
+        .into_iter()
+        .map(|item| {
+            let node = match item.node {
+                ListNode::Git(text) => expand_command(&text, aliases, chain, trace)?,
+                ListNode::Group(group) => {
+                    ListNode::Group(expand_items(group, aliases, chain, trace)?)
+                }
+                shell => shell,
+            };
+            Ok(ListItem {
+                op: item.op,
+                node,
+                filters: item.filters,
+                background: item.background,
+            })
+        })
+        .collect()
+}
+
+fn expand_command(
+    text: &str,
+    aliases: &[GitCmdAlias],
+    chain: &mut Vec<String>,
+    trace: &mut Vec<String>,
+) -> Result<ListNode, String> {
+    let (name, rest) = match text.split_once(char::is_whitespace) {
+        Some((name, rest)) => (name, rest.trim()),
+        None => (text, ""),
+    };
+
+    // An alias that starts with its own name (`log=log --oneline`) wraps the
+    // git command of the same name rather than recursing into itself
+    if chain.last().map(String::as_str) == Some(name) {
+        return Ok(ListNode::Git(text.to_string()));
+    }
+
+    let alias = match find_alias(name, aliases) {
+        Some(alias) => alias,
+        None => return Ok(ListNode::Git(text.to_string())),
+    };
+
+    if chain.iter().any(|seen| seen == name) {
+        let mut cycle = chain.clone();
+        cycle.push(name.to_string());
+        return Err(format!("Alias cycle detected: {}", cycle.join(" -> ")));
+    }
+    if chain.len() >= MAX_ALIAS_DEPTH {
+        return Err(format!(
+            "Alias expansion is nested deeper than {} levels: {} -> {}",
+            MAX_ALIAS_DEPTH,
+            chain.join(" -> "),
+            name
+        ));
+    }
+
+    // Shell aliases are handed to the shell as-is; it does its own `$1`
+    // handling and may contain `&&` of its own
+    if let Some(script) = alias.command.strip_prefix('!') {
+        trace.push(format!(
+            "{}{} -> {}",
+            "  ".repeat(chain.len()),
+            name,
+            alias.command
+        ));
+        return Ok(ListNode::Shell {
+            alias: name.to_string(),
+            script: script.trim().to_string(),
+            args: rest.to_string(),
+        });
+    }
+
+    // Redirections belong to the expanded command, not to its placeholders
+    let (rest, redirects) = strip_redirects(rest)?;
+    let body = substitute_params(alias, &split_words(&rest)?)?;
+    trace.push(format!("{}{} -> {}", "  ".repeat(chain.len()), name, body));
+
+    chain.push(name.to_string());
+    let expanded = expand_line(&body, aliases, chain, trace);
+    chain.pop();
+
+    let mut items = expanded?;
+    let node = if items.len() == 1 && items[0].filters.is_empty() && !items[0].background {
+        items.remove(0).node
+    } else {
+        ListNode::Group(items)
+    };
+    if redirects.is_empty() {
+        return Ok(node);
+    }
+    match node {
+        ListNode::Git(text) => Ok(ListNode::Git(format!("{} {}", text, redirects))),
+        ListNode::Shell {
+            alias,
+            script,
+            args,
+        } => Ok(ListNode::Shell {
+            alias,
+            script,
+            args: format!("{} {}", args, redirects).trim().to_string(),
+        }),
+        ListNode::Local(script) => Ok(ListNode::Local(format!("{} {}", script, redirects))),
+        ListNode::Group(_) => Err(format!(
+            "Cannot redirect the output of the compound alias '{}'.",
+            name
+        )),
+    }
+}
+
+// Output of the `which <name>` builtin
+pub fn describe_alias(name: &str, aliases: &[GitCmdAlias]) -> Vec<String> {
+    if find_alias(name, aliases).is_none() {
+        return vec![format!(
+            "'{}' is not an alias; it runs `git {}`.",
+            name, name
+        )];
+    }
+
+    match trace_aliases(name, aliases) {
+        Ok((expanded, mut lines)) => {
+            lines.push(format!("= {}", display_list(&expanded)));
+            lines
+        }
+        Err(e) => vec![e],
+    }
+}
+
+// Fills `$1`, `${1}`, `${1:-default}` and `$@` in the alias body from `args`,
+// the words typed after the alias name. They are spliced in as typed, so that
+// their quotes and expansions are handled once, when the command runs.
+// Arguments that no placeholder consumed are appended to the end.
+pub fn substitute_params(alias: &GitCmdAlias, args: &[String]) -> Result<String, String> {
+    let body: Vec<char> = alias.command.chars().collect();
+    let mut expanded = String::new();
+    // Which arguments a placeholder used, so the rest can be appended
+    let mut used = vec![false; args.len()];
+    let mut quote: Option<char> = None;
+    let mut i = 0;
+
+    while i < body.len() {
+        let c = body[i];
+        match (quote, c) {
+            (None, '\\') | (Some('"'), '\\') => {
+                expanded.extend(body.get(i..i + 2).unwrap_or(&body[i..]));
+                i += 2;
+                continue;
+            }
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            _ => {}
+        }
+        // Single-quoted text stays literal, as in the shell
+        if c != '$' || quote == Some('\'') || i + 1 >= body.len() {
+            expanded.push(c);
+            i += 1;
+            continue;
+        }
+        let in_quotes = quote == Some('"');
+
+        let next = body[i + 1];
+        if next == '@' {
+            // Inside quotes `"$@"` becomes a single word, like "$*" in sh
+            let words: Vec<String> = if in_quotes {
+                args.iter()
+                    .map(|arg| requote_in_double_quotes(arg))
+                    .collect()
+            } else {
+                args.to_vec()
+            };
+            expanded.push_str(&words.join(" "));
+            used.fill(true);
+            i += 2;
+        } else if next.is_ascii_digit() {
+            let end = (i + 1..body.len())
+                .find(|&j| !body[j].is_ascii_digit())
+                .unwrap_or(body.len());
+            let index: usize = body[i + 1..end]
+                .iter()
+                .collect::<String>()
+                .parse()
+                .unwrap_or(0);
+            expanded.push_str(&positional(alias, args, index, None, in_quotes)?);
+            mark_used(&mut used, index);
+            i = end;
+        } else if next == '{' {
+            let close = match body[i + 2..].iter().position(|&ch| ch == '}') {
+                Some(offset) => i + 2 + offset,
+                None => {
+                    return Err(format!(
+                        "Alias '{}' has an unterminated '${{' placeholder.",
+                        alias.identifier
+                    ))
+                }
+            };
+            let inner: String = body[i + 2..close].iter().collect();
+            let (index, default) = match inner.split_once(":-") {
+                Some((index, default)) => (index, Some(default)),
+                None => (inner.as_str(), None),
+            };
+            let index: usize = index.parse().map_err(|_| {
+                format!(
+                    "Alias '{}' has an invalid placeholder '${{{}}}'.",
+                    alias.identifier, inner
+                )
+            })?;
+            expanded.push_str(&positional(alias, args, index, default, in_quotes)?);
+            mark_used(&mut used, index);
+            i = close + 1;
+        } else {
+            expanded.push(c);
+            i += 1;
+        }
+    }
+
+    for (arg, _) in args.iter().zip(used).filter(|(_, used)| !used) {
+        expanded.push(' ');
+        expanded.push_str(arg);
+    }
+
+    Ok(expanded)
+}
+
+fn mark_used(used: &mut [bool], index: usize) {
+    if let Some(slot) = index.checked_sub(1).and_then(|i| used.get_mut(i)) {
+        *slot = true;
+    }
+}
+
+fn positional(
+    alias: &GitCmdAlias,
+    args: &[String],
+    index: usize,
+    default: Option<&str>,
+    in_quotes: bool,
+) -> Result<String, String> {
+    if index == 0 {
+        return Err(format!(
+            "Alias '{}' uses $0; positional parameters start at $1.",
+            alias.identifier
+        ));
+    }
+
+    match (args.get(index - 1), default) {
+        (Some(arg), _) if in_quotes => Ok(requote_in_double_quotes(arg)),
+        (Some(arg), _) => Ok(arg.clone()),
+        (None, Some(default)) => Ok(default.to_string()),
+        (None, None) => Err(format!(
+            "Alias '{}' requires argument ${} ('{}').",
+            alias.identifier, index, alias.command
+        )),
+    }
+}
+
+// Handles the `alias` and `unalias` REPL builtins, which change
+// `config.git_cmds` and write gitcmd.conf straight away. Returns false when
+// the line is not one of these builtins.
+pub fn handle_alias_builtin<W: Write>(
+    line: &str,
+    config: &mut GitCmdConfig,
+    dry_run: bool,
+    input_handler: &mut InputHandler<W>,
+) -> io::Result<bool> {
+    let (command, rest) = match line.split_once(char::is_whitespace) {
+        Some((command, rest)) => (command, rest.trim()),
+        None => (line, ""),
+    };
+
+    // A dry run still lists aliases but leaves gitcmd.conf alone
+    let changes = command == "unalias"
+        || (command == "alias" && (rest.starts_with("--edit") || rest.contains('=')));
+    if dry_run && changes {
+        input_handler.write_line("")?;
+        input_handler.write_line("Dry run: gitcmd.conf was not changed.")?;
+        return Ok(true);
+    }
+
+    match command {
+        "alias" if rest.is_empty() => {
+            input_handler.write_line("")?;
+            if config.git_cmds.is_empty() {
+                input_handler.write_line("No aliases defined.")?;
+            }
+            for alias in &config.git_cmds {
+                input_handler.write_line(&alias.to_entry())?;
+            }
+            return Ok(true);
+        }
+        "alias" => {
+            if let Some(name) = rest.strip_prefix("--edit") {
+                edit_alias(name.trim(), config, input_handler)?;
+            } else if let Some((name, body)) = rest.split_once('=') {
+                set_alias(
+                    name.trim(),
+                    &unquote_body(body.trim()),
+                    config,
+                    input_handler,
+                )?;
+            } else {
+                // `alias name` shows a single alias
+                input_handler.write_line("")?;
+                match find_alias(rest, &config.git_cmds) {
+                    Some(alias) => input_handler.write_line(&alias.to_entry())?,
+                    None => input_handler.write_line(&format!("No alias named '{}'.", rest))?,
+                }
+            }
+        }
+        "unalias" => {
+            input_handler.write_line("")?;
+            if rest.is_empty() {
+                input_handler.write_line("Usage: unalias <name>")?;
+                return Ok(true);
+            }
+            match config.git_cmds.iter().position(|a| a.identifier == rest) {
+                Some(index) => {
+                    config.git_cmds.remove(index);
+                    save_aliases(config, input_handler, &format!("Removed alias '{}'.", rest))?;
+                }
+                None => input_handler.write_line(&format!("No alias named '{}'.", rest))?,
+            }
+        }
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+fn edit_alias<W: Write>(
+    name: &str,
+    config: &mut GitCmdConfig,
+    input_handler: &mut InputHandler<W>,
+) -> io::Result<()> {
+    input_handler.write_line("")?;
+    let current = match find_alias(name, &config.git_cmds) {
+        Some(alias) => alias.command.clone(),
+        None => {
+            input_handler.write_line(&format!("No alias named '{}'.", name))?;
+            return Ok(());
+        }
+    };
+
+    // Pre-fill the line with the current body by treating it as a history entry
+    let prompt = format!("{}=", name);
+    match input_handler.read_line_with_history(&prompt, &[current], &mut Some(0))? {
+        Some(body) if !body.trim().is_empty() => {
+            set_alias(name, body.trim(), config, input_handler)
+        }
+        _ => input_handler.write_line("Alias left unchanged."),
+    }
+}
+
+fn set_alias<W: Write>(
+    name: &str,
+    body: &str,
+    config: &mut GitCmdConfig,
+    input_handler: &mut InputHandler<W>,
+) -> io::Result<()> {
+    input_handler.write_line("")?;
+    if let Err(e) = GitCmdAlias::check_name(name) {
+        return input_handler.write_line(&e);
+    }
+    if body.is_empty() {
+        return input_handler.write_line(&format!("Alias '{}' needs a command.", name));
+    }
+
+    let message = match config.git_cmds.iter_mut().find(|a| a.identifier == name) {
+        Some(alias) => {
+            alias.command = body.to_string();
+            format!("Updated alias '{}'.", name)
+        }
+        None => {
+            config.git_cmds.push(GitCmdAlias::new(name, body));
+            format!("Added alias '{}'.", name)
+        }
+    };
+    save_aliases(config, input_handler, &message)
+}
+
+fn save_aliases<W: Write>(
+    config: &GitCmdConfig,
+    input_handler: &mut InputHandler<W>,
+    message: &str,
+) -> io::Result<()> {
+    match write_gitcmd_conf(config) {
+        Some(e) => input_handler.write_line(&format!("Error writing config: {}", e)),
+        None => input_handler.write_line(message),
+    }
+}
+
+// `alias gac='add -A && commit'` is accepted the same way a shell would take it
+fn unquote_body(body: &str) -> String {
+    for quote in ['\'', '"'] {
+        if body.len() >= 2 && body.starts_with(quote) && body.ends_with(quote) {
+            let inner = &body[1..body.len() - 1];
+            if !inner.contains(quote) {
+                return inner.to_string();
+            }
+        }
+    }
+    body.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_parser::split_redirects;
+
+    // Binds the words typed after the alias, as typed, to its placeholders
+    fn expand(body: &str, typed: &str) -> Result<String, String> {
+        substitute_params(&GitCmdAlias::new("a", body), &split_words(typed)?)
+    }
+
+    #[test]
+    fn positional_parameters_are_filled() {
+        assert_eq!(
+            expand("push -u origin $1", "topic").unwrap(),
+            "push -u origin topic"
+        );
+        assert_eq!(
+            expand(r#"commit -m "$1""#, r#"'fix the "bug"'"#).unwrap(),
+            r#"commit -m "fix the \"bug\"""#
+        );
+        assert_eq!(
+            expand("log ${1:-main}..HEAD", "").unwrap(),
+            "log main..HEAD"
+        );
+        assert_eq!(
+            expand("log ${1:-main}..HEAD", "dev").unwrap(),
+            "log dev..HEAD"
+        );
+    }
+
+    #[test]
+    fn all_arguments_are_forwarded() {
+        assert_eq!(
+            expand("add $@", "a.txt 'my file'").unwrap(),
+            "add a.txt 'my file'"
+        );
+        assert_eq!(
+            expand(r#"commit -m "$@""#, "two words").unwrap(),
+            r#"commit -m "two words""#
+        );
+    }
+
+    #[test]
+    fn unused_arguments_are_appended() {
+        assert_eq!(expand("log --oneline", "-5").unwrap(), "log --oneline -5");
+        assert_eq!(
+            expand("log -1 --format=$2", "x %H").unwrap(),
+            "log -1 --format=%H x"
+        );
+        assert_eq!(expand("log '$1'", "x").unwrap(), "log '$1' x");
+    }
+
+    #[test]
+    fn missing_arguments_are_errors() {
+        assert_eq!(
+            expand("push origin $1", ""),
+            Err(String::from(
+                "Alias 'a' requires argument $1 ('push origin $1')."
+            ))
+        );
+        assert!(expand("log $0", "x").is_err());
+        assert!(expand("log ${1", "x").is_err());
+    }
+
+    #[test]
+    fn arguments_are_expanded_when_the_command_runs() {
+        assert_eq!(
+            expand("log --format=%s-$1", "$?").unwrap(),
+            "log --format=%s-$?"
+        );
+        assert_eq!(
+            expand("checkout -b $1-backup", "$(branch --show-current)").unwrap(),
+            "checkout -b $(branch --show-current)-backup"
+        );
+        assert_eq!(
+            expand(r#"commit -m "$1""#, r#"$USER' says "hi"' ~/x"#).unwrap(),
+            r#"commit -m "$USER says \"hi\"" ~/x"#
+        );
+        assert_eq!(
+            expand(r#"add "$1""#, "~/notes").unwrap(),
+            r#"add "${HOME}/notes""#
+        );
+    }
+
+    fn aliases(entries: &[(&str, &str)]) -> Vec<GitCmdAlias> {
+        entries
+            .iter()
+            .map(|(name, body)| GitCmdAlias::new(name, body))
+            .collect()
+    }
+
+    fn expanded_line(line: &str, aliases: &[GitCmdAlias]) -> Result<String, String> {
+        expand_aliases(line, aliases).map(|items| display_list(&items))
+    }
+
+    #[test]
+    fn aliases_resolve_recursively() {
+        let aliases = aliases(&[("sync", "fetch --all && rb"), ("rb", "rebase $1")]);
+        assert_eq!(
+            expanded_line("sync main", &aliases).unwrap(),
+            "(git fetch --all && git rebase main)"
+        );
+    }
+
+    #[test]
+    fn an_alias_may_wrap_the_command_of_its_name() {
+        let aliases = aliases(&[("log", "log --oneline"), ("l", "log -5")]);
+        assert_eq!(
+            expanded_line("log --all", &aliases).unwrap(),
+            "git log --oneline --all"
+        );
+        assert_eq!(
+            expanded_line("l", &aliases).unwrap(),
+            "git log --oneline -5"
+        );
+    }
+
+    #[test]
+    fn alias_cycles_are_named() {
+        let aliases = aliases(&[("a", "b"), ("b", "a")]);
+        assert_eq!(
+            expanded_line("a", &aliases),
+            Err(String::from("Alias cycle detected: a -> b -> a"))
+        );
+    }
+
+    #[test]
+    fn alias_nesting_is_limited() {
+        let chain = |depth: usize| -> Vec<GitCmdAlias> {
+            let mut aliases: Vec<GitCmdAlias> = (0..depth)
+                .map(|i| GitCmdAlias::new(&format!("a{}", i), &format!("a{}", i + 1)))
+                .collect();
+            aliases.push(GitCmdAlias::new(&format!("a{}", depth), "status"));
+            aliases
+        };
+        assert_eq!(
+            expanded_line("a0", &chain(MAX_ALIAS_DEPTH - 1)).unwrap(),
+            "git status"
+        );
+        let error = expanded_line("a0", &chain(MAX_ALIAS_DEPTH)).unwrap_err();
+        assert!(error.starts_with("Alias expansion is nested deeper than 16 levels: a0 -> a1"));
+    }
+
+    #[test]
+    fn redirects_after_an_alias_are_kept() {
+        let aliases = aliases(&[("lg", "log --oneline"), ("gac", "add -A && commit")]);
+        let items = expand_aliases("lg -5 > notes.txt 2> err.txt", &aliases).unwrap();
+        let text = match &items[0].node {
+            ListNode::Git(text) => text,
+            node => panic!("expected a git command, got {:?}", node),
+        };
+        let (text, redirects) = split_redirects(text).unwrap();
+        assert_eq!(text, "log --oneline -5");
+        let paths: Vec<&str> = redirects.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(paths, ["notes.txt", "err.txt"]);
+        assert!(redirects[1].stderr);
+
+        assert!(expand_aliases("gac > out.txt", &aliases).is_err());
+    }
+}
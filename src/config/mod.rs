@@ -1,5 +1,6 @@
 use crate::config_io::{
-    import_git_config, source_gitcmd_conf, write_gitcmd_conf, GitCmdAlias, GitCmdConfig, GitConfig,
+    import_git_config, read_gitcmd_conf, source_gitcmd_conf, write_gitcmd_conf, GitCmdAlias,
+    GitCmdConfig, GitConfig,
 };
 use crate::input_handler::InputHandler;
 
@@ -281,10 +282,103 @@ impl GitConfigSettings {
         }
         config_scope
     }
+    pub fn import_git_aliases(
+        &self,
+        imported: &[GitConfig],
+        aliases: &mut Vec<GitCmdAlias>,
+    ) -> io::Result<()> {
+        let git_aliases: Vec<GitCmdAlias> = imported
+            .iter()
+            .filter_map(GitCmdAlias::from_git_alias)
+            .collect();
+        if git_aliases.is_empty() {
+            return Ok(());
+        }
+
+        let mut input_handler = InputHandler::new_raw()?;
+        input_handler.clear_screen()?;
+        input_handler.write_line(&format!(
+            "Found {} git aliases in your git config:",
+            git_aliases.len()
+        ))?;
+        for alias in &git_aliases {
+            input_handler.write_line(&format!("\t{}", alias.to_entry()))?;
+        }
+
+        loop {
+            let input = input_handler
+                .read_line("Do you want to import them as gitcmd aliases (Y/N)? ")?
+                .unwrap_or_default();
+            match input.trim().to_uppercase().as_str() {
+                "Y" | "" => break,
+                "N" => return Ok(()),
+                _ => input_handler.write_line("\nInvalid input. Please enter 'Y' or 'N'.")?,
+            }
+        }
+
+        for alias in git_aliases {
+            let existing = match aliases
+                .iter()
+                .position(|a| a.identifier == alias.identifier)
+            {
+                Some(index) => index,
+                None => {
+                    aliases.push(alias);
+                    continue;
+                }
+            };
+            if aliases[existing].command == alias.command {
+                continue;
+            }
+
+            input_handler.write_line(&format!(
+                "\nAlias '{}' already exists in gitcmd.",
+                alias.identifier
+            ))?;
+            input_handler.write_line(&format!("\tgitcmd: {}", aliases[existing].command))?;
+            input_handler.write_line(&format!("\tgit:    {}", alias.command))?;
+
+            loop {
+                let input = input_handler
+                    .read_line("Keep the gitcmd alias (K), replace it (R), or import under a new name (N)? ")?
+                    .unwrap_or_default();
+                match input.trim().to_uppercase().as_str() {
+                    "K" | "" => break,
+                    "R" => {
+                        aliases[existing] = alias;
+                        break;
+                    }
+                    "N" => {
+                        let name = input_handler
+                            .read_line("New alias name: ")?
+                            .unwrap_or_default();
+                        let name = name.trim();
+                        if let Err(e) = GitCmdAlias::check_name(name) {
+                            input_handler.write_line(&format!("\n{}", e))?;
+                        } else if aliases.iter().any(|a| a.identifier == name) {
+                            input_handler
+                                .write_line(&format!("\nAlias '{}' is already taken.", name))?;
+                        } else {
+                            aliases.push(GitCmdAlias::new(name, &alias.command));
+                            break;
+                        }
+                    }
+                    _ => input_handler
+                        .write_line("\nInvalid input. Please enter 'K', 'R' or 'N'.")?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn display(&self) -> io::Result<()> {
         let mut input_handler = InputHandler::new_raw()?;
         let mut git_configs: Vec<GitConfig> = Vec::new();
-        let mut git_cmd_aliases: Vec<GitCmdAlias> = Vec::new();
+        // Start from what is already in gitcmd.conf so setup does not drop
+        // the aliases, the dangerous command patterns or the workspace
+        let existing = read_gitcmd_conf().unwrap_or_default();
+        let mut git_cmd_aliases: Vec<GitCmdAlias> = existing.git_cmds;
 
         // Import existing settings if requested
         let mut import_scope = self.set_import_options();
@@ -295,6 +389,7 @@ impl GitConfigSettings {
             import_scope = Vec::new();
         }
         let import_settings = import_git_config(import_scope).unwrap_or_default();
+        self.import_git_aliases(&import_settings, &mut git_cmd_aliases)?;
         let config_scope: String = self.set_creation_scope();
         if config_scope.is_empty() {
             return Ok(());
@@ -379,6 +474,8 @@ impl GitConfigSettings {
             scope: config_scope.clone(),
             git_configs,
             git_cmds: git_cmd_aliases,
+            dangerous: existing.dangerous,
+            workspace: existing.workspace,
         };
 
         // Confirm before applying changes
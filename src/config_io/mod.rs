@@ -16,13 +16,30 @@
 //      a=add -A
 //      gac=add -A && commit
 // }
+//
+// dangerous {
+//      push --force
+//      reset --hard
+// }
+//
+// workspace {
+//      ~/src/services/*
+//      web=~/src/frontend
+// }
 
 use crate::input_handler::InputHandler;
-use std::fs::{File, OpenOptions};
+use dirs::home_dir;
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
 use std::process::Command;
 
-const CONFIG_FILE: &str = "~/.config/gitcmd/gitcmd.conf";
+const CONFIG_FILE: &str = ".config/gitcmd/gitcmd.conf";
+
+// `~` is not expanded by the filesystem, so resolve the file against $HOME
+fn config_file_path() -> PathBuf {
+    home_dir().unwrap_or_default().join(CONFIG_FILE)
+}
 
 #[derive(Debug, Clone)]
 pub struct GitCmdAlias {
@@ -50,6 +67,29 @@ impl GitCmdAlias {
             None
         }
     }
+
+    // The config parser treats any key with a `.` as a git setting, and the
+    // first `=` of an entry ends the name
+    pub fn check_name(name: &str) -> Result<(), String> {
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '.' || c == '=') {
+            return Err(format!(
+                "Invalid alias name '{}': names cannot be empty or contain spaces, '.' or '='.",
+                name
+            ));
+        }
+        Ok(())
+    }
+
+    // Converts an imported `alias.co=checkout` entry. Shell aliases keep their
+    // leading `!`, which gitcmd runs the same way git does. Names gitcmd can't
+    // store are skipped.
+    pub fn from_git_alias(config: &GitConfig) -> Option<Self> {
+        let name = config.identifier.strip_prefix("alias.")?;
+        if GitCmdAlias::check_name(name).is_err() || config.value.trim().is_empty() {
+            return None;
+        }
+        Some(GitCmdAlias::new(name, &config.value))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -92,6 +132,11 @@ pub struct GitCmdConfig {
     pub scope: String,
     pub git_configs: Vec<GitConfig>,
     pub git_cmds: Vec<GitCmdAlias>,
+    // Commands to confirm before running. `None` when the file has no
+    // `dangerous` section, in which case the defaults apply.
+    pub dangerous: Option<Vec<String>>,
+    // Repositories for `@all`, as paths or globs, optionally named
+    pub workspace: Vec<String>,
 }
 
 impl GitCmdConfig {
@@ -100,15 +145,41 @@ impl GitCmdConfig {
             scope: String::new(),
             git_configs: Vec::new(),
             git_cmds: Vec::new(),
+            dangerous: None,
+            workspace: Vec::new(),
         }
     }
 
-    fn parse_line(&mut self, line: &str) {
+    fn parse_line(&mut self, line: &str, section: &mut String) {
         let line = line.trim();
         if line.is_empty() || line.starts_with("//") {
             return;
         }
 
+        if let Some(name) = line.strip_suffix('{') {
+            *section = name.trim().to_string();
+            // An empty section turns the defaults off
+            if section == "dangerous" {
+                self.dangerous = Some(Vec::new());
+            }
+            return;
+        }
+        if line == "}" {
+            section.clear();
+            return;
+        }
+        // These sections can't be told apart by the shape of their lines
+        if section == "dangerous" {
+            self.dangerous
+                .get_or_insert_with(Vec::new)
+                .push(line.to_string());
+            return;
+        }
+        if section == "workspace" {
+            self.workspace.push(line.to_string());
+            return;
+        }
+
         if !line.contains('{') && !line.contains('}') && !line.contains('=') {
             // Must be a scope value
             self.scope = line.trim().to_string();
@@ -155,8 +226,8 @@ pub fn import_git_config(scopes: Vec<String>) -> Result<Vec<GitConfig>, std::io:
         return Ok(Vec::new());
     }
     let mut configs: Vec<GitConfig> = Vec::new();
-    let mut input_handler = InputHandler::new_raw()
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+    let mut input_handler =
+        InputHandler::new_raw().map_err(|e| std::io::Error::other(e.to_string()))?;
 
     loop {
         let input = input_handler
@@ -205,23 +276,31 @@ pub fn import_git_config(scopes: Vec<String>) -> Result<Vec<GitConfig>, std::io:
 }
 
 pub fn read_gitcmd_conf() -> Result<GitCmdConfig, io::Error> {
-    let file = File::open(CONFIG_FILE)?;
+    let file = File::open(config_file_path())?;
     let reader = BufReader::new(file);
     let mut config = GitCmdConfig::new();
+    let mut section = String::new();
 
     for line in reader.lines() {
-        config.parse_line(&line?);
+        config.parse_line(&line?, &mut section);
     }
 
     Ok(config)
 }
 
 pub fn write_gitcmd_conf(config: &GitCmdConfig) -> Option<io::Error> {
+    let path = config_file_path();
+    if let Some(parent) = path.parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            return Some(e);
+        }
+    }
+
     let file = OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
-        .open(CONFIG_FILE);
+        .open(path);
 
     match file {
         Ok(mut file) => {
@@ -246,6 +325,24 @@ pub fn write_gitcmd_conf(config: &GitCmdConfig) -> Option<io::Error> {
             }
             content.push_str("}\n");
 
+            // Write dangerous section, if there is one
+            if let Some(patterns) = &config.dangerous {
+                content.push_str("\ndangerous {\n");
+                for pattern in patterns {
+                    content.push_str(&format!("\t{}\n", pattern));
+                }
+                content.push_str("}\n");
+            }
+
+            // Write workspace section, if there is one
+            if !config.workspace.is_empty() {
+                content.push_str("\nworkspace {\n");
+                for entry in &config.workspace {
+                    content.push_str(&format!("\t{}\n", entry));
+                }
+                content.push_str("}\n");
+            }
+
             if let Err(e) = file.write_all(content.as_bytes()) {
                 return Some(e);
             }
@@ -270,3 +367,32 @@ pub fn source_gitcmd_conf() -> Option<io::Error> {
         Err(e) => Some(e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn imported(identifier: &str, value: &str) -> Option<GitCmdAlias> {
+        GitCmdAlias::from_git_alias(&GitConfig::new(identifier, value))
+    }
+
+    #[test]
+    fn git_aliases_are_imported() {
+        let alias = imported("alias.co", "checkout").unwrap();
+        assert_eq!(alias.to_entry(), "co=checkout");
+        let alias = imported("alias.lg", "!git log --oneline | head").unwrap();
+        assert_eq!(alias.command, "!git log --oneline | head");
+
+        assert!(imported("user.name", "someone").is_none());
+        assert!(imported("alias.empty", " ").is_none());
+        assert!(imported("alias.foo.bar", "status").is_none());
+    }
+
+    #[test]
+    fn alias_names_are_checked() {
+        assert!(GitCmdAlias::check_name("co").is_ok());
+        for name in ["", "two words", "foo.bar", "a=b"] {
+            assert!(GitCmdAlias::check_name(name).is_err(), "{}", name);
+        }
+    }
+}
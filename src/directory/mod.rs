@@ -0,0 +1,149 @@
+// The `cd`, `pwd`, `pushd` and `popd` builtins. They change gitcmd's own
+// working directory, which every command it starts inherits.
+
+use crate::executor::repo_root;
+use crate::input_handler::InputHandler;
+use crate::input_parser::{parse_command_list, tokenize, ListNode};
+use dirs::home_dir;
+use std::env;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub const DIRECTORY_BUILTINS: [&str; 4] = ["cd", "pwd", "pushd", "popd"];
+
+// Directories left behind, for `cd -` and `popd`
+#[derive(Default)]
+pub struct Directories {
+    previous: Option<PathBuf>,
+    stack: Vec<PathBuf>,
+}
+
+// `path` with the home directory shortened to `~`, as shown in the prompt
+pub fn display_path(path: &Path) -> String {
+    match home_dir().and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(rest) if rest.as_os_str().is_empty() => String::from("~"),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.display().to_string(),
+    }
+}
+
+pub fn current_dir_display() -> String {
+    env::current_dir()
+        .map(|dir| display_path(&dir))
+        .unwrap_or_else(|_| String::from("?"))
+}
+
+impl Directories {
+    // Moves to `target` and remembers where we were. Shells keep `PWD` and
+    // `OLDPWD` up to date, so `$PWD` and `!` commands see the move too.
+    fn change_to(&mut self, target: &Path) -> Result<(), String> {
+        let current = env::current_dir().ok();
+        env::set_current_dir(target).map_err(|e| format!("{}: {}", target.display(), e))?;
+        if let Some(current) = &current {
+            env::set_var("OLDPWD", current);
+        }
+        if let Ok(dir) = env::current_dir() {
+            env::set_var("PWD", dir);
+        }
+        self.previous = current;
+        Ok(())
+    }
+
+    fn stack_line(&self) -> String {
+        let mut line = current_dir_display();
+        for dir in self.stack.iter().rev() {
+            line.push(' ');
+            line.push_str(&display_path(dir));
+        }
+        line
+    }
+
+    // Runs one of the builtins and returns the lines to show. Errors are
+    // prefixed with the name of the builtin when they are shown.
+    fn run(&mut self, command: &str, args: &[String]) -> Result<Vec<String>, String> {
+        match (command, args) {
+            ("pwd", []) => Ok(vec![current_dir_display()]),
+            // Without a directory, `cd` goes to the top of the repository,
+            // or home outside of one
+            ("cd", []) => {
+                let target = repo_root()
+                    .or_else(home_dir)
+                    .ok_or("no repository or home directory to go to")?;
+                self.change_to(&target)?;
+                Ok(Vec::new())
+            }
+            ("cd", [dir]) if dir == "-" => {
+                let target = self.previous.clone().ok_or("no previous directory")?;
+                self.change_to(&target)?;
+                Ok(vec![current_dir_display()])
+            }
+            ("cd", [dir]) => {
+                self.change_to(Path::new(dir))?;
+                Ok(Vec::new())
+            }
+            // Without a directory, `pushd` swaps with the top of the stack
+            ("pushd", []) => {
+                let target = self.stack.pop().ok_or("no other directory")?;
+                let current = env::current_dir().map_err(|e| e.to_string())?;
+                if let Err(e) = self.change_to(&target) {
+                    self.stack.push(target);
+                    return Err(e);
+                }
+                self.stack.push(current);
+                Ok(vec![self.stack_line()])
+            }
+            ("pushd", [dir]) => {
+                let current = env::current_dir().map_err(|e| e.to_string())?;
+                self.change_to(Path::new(dir))?;
+                self.stack.push(current);
+                Ok(vec![self.stack_line()])
+            }
+            ("popd", []) => {
+                let target = self.stack.last().ok_or("directory stack empty")?;
+                self.change_to(&target.clone())?;
+                self.stack.pop();
+                Ok(vec![self.stack_line()])
+            }
+            _ => Err(String::from("too many arguments")),
+        }
+    }
+}
+
+// Runs `cd`, `pwd`, `pushd` and `popd` and returns their exit code, or `None`
+// when the line is none of them
+pub fn handle_directory_builtin<W: Write>(
+    line: &str,
+    directories: &mut Directories,
+    input_handler: &mut InputHandler<W>,
+) -> io::Result<Option<i32>> {
+    let command = match line.split_whitespace().next() {
+        Some(command) if DIRECTORY_BUILTINS.contains(&command) => command,
+        _ => return Ok(None),
+    };
+    // Combined with other commands, the line is left to the executor, which
+    // refuses these builtins there
+    let alone = parse_command_list(line).is_ok_and(|items| {
+        matches!(items.as_slice(), [item] if matches!(item.node, ListNode::Git(_))
+            && item.filters.is_empty()
+            && !item.background)
+    });
+    if !alone {
+        return Ok(None);
+    }
+
+    input_handler.write_line("")?;
+    // Quotes, `~` and `$VAR` work the same as in any other command
+    let result = tokenize(&line[command.len()..]).and_then(|args| directories.run(command, &args));
+    match result {
+        Ok(lines) => {
+            for line in lines {
+                input_handler.write_line(&line)?;
+            }
+            Ok(Some(0))
+        }
+        Err(e) => {
+            input_handler.write_line(&format!("{}: {}", command, e))?;
+            Ok(Some(1))
+        }
+    }
+}
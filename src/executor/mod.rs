@@ -0,0 +1,776 @@
+use crate::input_parser::{quote_arg, Redirect};
+use crate::jobs::{JobState, Jobs};
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal, Read, Stdout, Write};
+use std::mem;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+use termion::raw::RawTerminal;
+
+thread_local! {
+    // Exit code of the last command that ran, for `$?` and the prompt. Each
+    // background job keeps its own.
+    static LAST_STATUS: Cell<i32> = const { Cell::new(0) };
+    // The background job run by this thread, which keeps what it prints
+    static CURRENT_JOB: RefCell<Option<Arc<JobState>>> = const { RefCell::new(None) };
+}
+
+pub fn last_status() -> i32 {
+    LAST_STATUS.with(Cell::get)
+}
+
+pub fn set_last_status(status: i32) {
+    LAST_STATUS.with(|last| last.set(status));
+}
+
+pub fn set_current_job(job: Option<Arc<JobState>>) {
+    CURRENT_JOB.with(|current| *current.borrow_mut() = job);
+}
+
+fn current_job() -> Option<Arc<JobState>> {
+    CURRENT_JOB.with(|current| current.borrow().clone())
+}
+
+pub fn in_background_job() -> bool {
+    CURRENT_JOB.with(|current| current.borrow().is_some())
+}
+
+// A background job keeps running in the directory it was started in, even
+// after `cd` has moved the REPL elsewhere
+fn job_dir() -> Option<PathBuf> {
+    current_job().and_then(|job| job.dir().map(Path::to_path_buf))
+}
+
+fn in_job_dir(command: &mut Command) -> &mut Command {
+    if let Some(dir) = job_dir() {
+        command.current_dir(dir);
+    }
+    command
+}
+
+// The directory relative paths are resolved against
+pub fn working_dir() -> PathBuf {
+    job_dir().unwrap_or_else(|| PathBuf::from("."))
+}
+
+pub fn print_error(message: &str) {
+    // A background job must not print over the line being typed
+    if let Some(job) = current_job() {
+        job.write(format!("{}\n", message).as_bytes());
+        return;
+    }
+    // Outside the REPL, errors go where scripts expect them
+    if !io::stdout().is_terminal() {
+        eprintln!("{}", message);
+        return;
+    }
+    print_line(message);
+}
+
+// Prints a line of gitcmd's own to stdout, whether or not the terminal is in
+// raw mode
+pub fn print_line(line: &str) {
+    // A background job keeps what it prints for `fg`
+    if let Some(job) = current_job() {
+        job.write(format!("{}\n", line).as_bytes());
+        return;
+    }
+    let result = if io::stdout().is_terminal() {
+        let line = line.replace('\n', "\r\n\x1b[K");
+        writeln!(io::stdout(), "\r\n\x1b[K{}", line)
+    } else {
+        writeln!(io::stdout(), "{}", line)
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to write to stdout: {}", e);
+    }
+}
+
+// Writes child output under raw mode, where a bare `\n` does not return the
+// cursor. A `\r` is passed through so progress meters keep redrawing one line.
+pub fn print_output(output: &[u8]) {
+    let mut translated = Vec::with_capacity(output.len());
+    for &byte in output {
+        if byte == b'\n' {
+            translated.extend_from_slice(b"\r\n\x1b[K");
+        } else {
+            translated.push(byte);
+        }
+    }
+    let mut stdout = io::stdout();
+    if let Err(e) = stdout.write_all(&translated) {
+        eprintln!("Failed to write to stdout: {}", e);
+    }
+    if let Err(e) = stdout.flush() {
+        eprintln!("Failed to flush stdout: {}", e);
+    }
+}
+
+pub fn git_command(args: &[String], color: bool) -> Command {
+    // Create the command and add the common arguments
+    let mut command = Command::new("git");
+    if color {
+        command.arg("-c").arg("color.ui=always");
+    }
+    command.arg("--no-pager").args(args);
+    command
+}
+
+// Commands that only draw their progress meters when stderr is a terminal
+const PROGRESS_COMMANDS: [&str; 4] = ["clone", "fetch", "pull", "push"];
+
+// Stderr of a child is a pipe to gitcmd, so git has to be asked for progress
+// explicitly unless the user already chose
+pub fn request_progress(args: &mut Vec<String>) {
+    let wants_choice = args
+        .first()
+        .is_some_and(|command| PROGRESS_COMMANDS.contains(&command.as_str()));
+    let chosen = args.iter().any(|arg| {
+        matches!(
+            arg.as_str(),
+            "--progress" | "--no-progress" | "-q" | "--quiet"
+        )
+    });
+    if wants_choice && !chosen {
+        args.insert(1, String::from("--progress"));
+    }
+}
+
+// Whether `args` contain one of the given options. Short options also count
+// inside clusters like `-am`.
+fn has_option(args: &[String], short: &str, long: &[&str]) -> bool {
+    args.iter()
+        .take_while(|arg| *arg != "--")
+        .any(|arg| match arg.strip_prefix("--") {
+            Some(name) => long.contains(&name.split('=').next().unwrap_or(name)),
+            None => arg
+                .strip_prefix('-')
+                .is_some_and(|flags| flags.chars().any(|flag| short.contains(flag))),
+        })
+}
+
+// Commands that open an editor or ask questions on the terminal, and so need
+// it handed over instead of having their output piped through gitcmd
+pub fn is_interactive(args: &[String]) -> bool {
+    let (command, options) = match args.split_first() {
+        Some(split) => split,
+        None => return false,
+    };
+    let has = |short: &str, long: &[&str]| has_option(options, short, long);
+    let message = |short: &str| has(short, &["message", "file", "reuse-message"]);
+
+    match command.as_str() {
+        "commit" => {
+            has("ce", &["edit", "reedit-message"])
+                || !(message("mFC") || has("", &["no-edit", "fixup"]))
+        }
+        "tag" => has("e", &["edit"]) || (has("as", &["annotate", "sign"]) && !message("mF")),
+        "notes" => {
+            matches!(
+                options.first().map(String::as_str),
+                Some("add" | "append" | "edit")
+            ) && !message("mFC")
+        }
+        "add" => has("pie", &["patch", "interactive", "edit"]),
+        "checkout" | "reset" | "restore" => has("p", &["patch"]),
+        // `stash show -p` only prints a diff
+        "stash" => {
+            has("p", &["patch"])
+                && !matches!(options.first().map(String::as_str), Some("list" | "show"))
+        }
+        "clean" | "am" => has("i", &["interactive"]),
+        "rebase" => has("i", &["interactive", "edit-todo", "continue"]),
+        "merge" | "cherry-pick" => has("e", &["edit"]),
+        "revert" => !has("n", &["no-edit", "no-commit", "abort", "quit", "skip"]),
+        "config" => has("e", &["edit"]),
+        "branch" => has("", &["edit-description"]),
+        "mergetool" | "difftool" | "citool" | "gui" | "help" => true,
+        _ => false,
+    }
+}
+
+// Runs git without input, for a command whose output gitcmd reads, and
+// returns what it printed without the trailing newlines, or its error
+pub fn capture_git<S: AsRef<str>>(args: &[S]) -> Result<String, String> {
+    let output = capture_output(args)?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    while text.ends_with('\n') || text.ends_with('\r') {
+        text.pop();
+    }
+    Ok(text)
+}
+
+// Like `capture_git`, but returns the non-empty lines of both streams, or
+// nothing when git failed. `push --dry-run` reports on stderr.
+pub fn capture_git_lines<S: AsRef<str>>(args: &[S]) -> Vec<String> {
+    let output = match capture_output(args) {
+        Ok(output) if output.status.success() => output,
+        _ => return Vec::new(),
+    };
+    [output.stdout, output.stderr]
+        .iter()
+        .flat_map(|stream| {
+            String::from_utf8_lossy(stream)
+                .lines()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .filter(|line| !line.trim().is_empty())
+        .collect()
+}
+
+fn capture_output<S: AsRef<str>>(args: &[S]) -> Result<process::Output, String> {
+    let args: Vec<String> = args.iter().map(|arg| arg.as_ref().to_string()).collect();
+    in_job_dir(&mut git_command(&args, false))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))
+}
+
+// Directory that git's own `!` aliases run in: the top of the work tree, or
+// the current directory outside of a repository
+pub fn repo_root() -> Option<PathBuf> {
+    let output = in_job_dir(&mut Command::new("git"))
+        .args(["rev-parse", "--show-toplevel"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(PathBuf::from(
+        String::from_utf8_lossy(&output.stdout).trim(),
+    ))
+}
+
+pub fn shell_command(script: &str) -> Command {
+    let shell = env::var("SHELL").unwrap_or_else(|_| String::from("sh"));
+    let mut command = Command::new(shell);
+    command.arg("-c").arg(script);
+    command
+}
+
+pub fn shell_alias_command(alias: &str, script: &str, args: &[String]) -> Command {
+    // Same convention as git: the arguments become "$@" and are appended to
+    // the script, and GIT_PREFIX holds the subdirectory the user was in
+    let mut command = shell_command(&format!("{} \"$@\"", script));
+    command.arg(alias).args(args);
+    if let Some(root) = repo_root() {
+        let prefix = in_job_dir(&mut Command::new("git"))
+            .args(["rev-parse", "--show-prefix"])
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .unwrap_or_default();
+        command.current_dir(root).env("GIT_PREFIX", prefix);
+    }
+    command
+}
+
+// One process of a pipeline, with the redirections typed for it
+pub struct PipelineStage {
+    pub command: Command,
+    pub redirects: Vec<Redirect>,
+    pub interactive: bool,
+}
+
+// The command line a pipeline would run, quoted so that it can be pasted
+// into a shell
+pub fn describe_pipeline(pipeline: &[PipelineStage]) -> String {
+    let stages: Vec<String> = pipeline
+        .iter()
+        .map(|stage| {
+            let program = stage.command.get_program().to_string_lossy();
+            let mut words = vec![quote_arg(&program)];
+            for arg in stage.command.get_args() {
+                words.push(quote_arg(&arg.to_string_lossy()));
+            }
+            for redirect in &stage.redirects {
+                let operator = match (redirect.stderr, redirect.append) {
+                    (false, false) => ">",
+                    (false, true) => ">>",
+                    (true, false) => "2>",
+                    (true, true) => "2>>",
+                };
+                words.push(format!("{} {}", operator, quote_arg(&redirect.path)));
+            }
+            words.join(" ")
+        })
+        .collect();
+    stages.join(" | ")
+}
+
+struct RunningStage {
+    name: String,
+    child: Child,
+}
+
+// A chunk of output from one of the running stages
+enum Output {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+}
+
+// Forwards everything a child writes to `sender`, one chunk per complete line.
+// A line ends at `\n`, or at `\r` for progress updates that redraw in place.
+fn stream_lines(
+    mut reader: impl Read + Send + 'static,
+    sender: Sender<Output>,
+    wrap: fn(Vec<u8>) -> Output,
+) {
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        let mut pending = Vec::new();
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            pending.extend_from_slice(&buffer[..read]);
+            if let Some(end) = pending.iter().rposition(|&b| b == b'\n' || b == b'\r') {
+                let rest = pending.split_off(end + 1);
+                if sender.send(wrap(mem::replace(&mut pending, rest))).is_err() {
+                    return;
+                }
+            }
+        }
+        // Whatever is left had no line ending, like a prompt
+        if !pending.is_empty() {
+            let _ = sender.send(wrap(pending));
+        }
+    });
+}
+
+// Like a shell, a process killed by a signal reports 128 plus its number
+fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+fn open_redirect(redirect: &Redirect) -> Result<File, String> {
+    let mut options = OpenOptions::new();
+    options.create(true);
+    if redirect.append {
+        options.append(true);
+    } else {
+        options.write(true).truncate(true);
+    }
+    // Relative paths start where the command runs, which for a job is where
+    // it was started
+    options
+        .open(working_dir().join(&redirect.path))
+        .map_err(|e| format!("Cannot open '{}' for writing: {}", redirect.path, e))
+}
+
+fn spawn_stages(
+    pipeline: Vec<PipelineStage>,
+    running: &mut Vec<RunningStage>,
+    output: &Sender<Output>,
+    inherit: bool,
+    detach: bool,
+) -> Result<(), (i32, String)> {
+    let count = pipeline.len();
+    for stage in pipeline {
+        let mut command = stage.command;
+        let name = command.get_program().to_string_lossy().to_string();
+        if command.get_current_dir().is_none() {
+            in_job_dir(&mut command);
+        }
+
+        match running.last_mut() {
+            // A stage whose output was redirected leaves nothing for the next one
+            Some(previous) => match previous.child.stdout.take() {
+                Some(stdout) => command.stdin(stdout),
+                None => command.stdin(Stdio::null()),
+            },
+            None if detach => command.stdin(Stdio::null()),
+            None => command.stdin(Stdio::inherit()),
+        };
+        // Output that gitcmd does not handle itself goes straight to the
+        // terminal, except where one stage feeds the next
+        if inherit {
+            let last = running.len() + 1 == count;
+            command
+                .stdout(if last {
+                    Stdio::inherit()
+                } else {
+                    Stdio::piped()
+                })
+                .stderr(Stdio::inherit());
+        } else {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        // Later redirections of the same stream win, like in a shell
+        for redirect in &stage.redirects {
+            let file = open_redirect(redirect).map_err(|e| (1, e))?;
+            if redirect.stderr {
+                command.stderr(file);
+            } else {
+                command.stdout(file);
+            }
+        }
+
+        restore_interrupt(&mut command);
+        if detach {
+            detach_from_terminal(&mut command);
+        }
+        let mut child = command.spawn().map_err(|e| {
+            // The codes a shell uses for a missing or unusable program
+            let status = if e.kind() == io::ErrorKind::NotFound {
+                127
+            } else {
+                126
+            };
+            (status, format!("Failed to run '{}': {}", name, e))
+        })?;
+        // Every stage reports errors to the terminal, but only the last one's
+        // output is not consumed by another stage
+        if let Some(stderr) = child.stderr.take() {
+            stream_lines(stderr, output.clone(), Output::Stderr);
+        }
+        if running.len() + 1 == count {
+            if let Some(stdout) = child.stdout.take() {
+                stream_lines(stdout, output.clone(), Output::Stdout);
+            }
+        }
+        running.push(RunningStage { name, child });
+    }
+
+    Ok(())
+}
+
+// Background jobs get a session of their own: Ctrl-C at the prompt doesn't
+// reach them, and they can't stop waiting for input from the terminal. The
+// session also makes each stage the leader of a process group.
+fn detach_from_terminal(command: &mut Command) {
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+}
+
+// How the commands of a line are run
+pub struct RunOptions<'a> {
+    // The REPL's raw terminal, which interactive commands need switched back
+    // to normal mode
+    pub terminal: Option<&'a RawTerminal<Stdout>>,
+    // Whether output taller than the terminal goes through a pager
+    pub pager: bool,
+    // Where `&` starts background jobs. Without it they run in the foreground.
+    pub jobs: Option<&'a Jobs>,
+    // Print what would run instead of running it
+    pub dry_run: bool,
+    // Patterns of git commands to confirm before running
+    pub guard: &'a [String],
+}
+
+// Exit code of a command stopped with Ctrl-C
+pub const INTERRUPTED: i32 = 128 + libc::SIGINT;
+
+// Puts the terminal back in normal mode while commands run. Ctrl-C then sends
+// SIGINT to the foreground process group: the children, and gitcmd itself,
+// which ignores it so that only the running command is stopped.
+fn hand_over_terminal(terminal: &RawTerminal<Stdout>) {
+    if let Err(e) = terminal.suspend_raw_mode() {
+        print_error(&format!("Failed to restore the terminal: {}", e));
+    }
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_IGN);
+    }
+}
+
+fn take_back_terminal(terminal: &RawTerminal<Stdout>) {
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+    if let Err(e) = terminal.activate_raw_mode() {
+        print_error(&format!("Failed to enter raw mode: {}", e));
+    }
+}
+
+// An ignored signal stays ignored across exec, so children have to be given
+// the default Ctrl-C behavior back
+fn restore_interrupt(command: &mut Command) {
+    unsafe {
+        command.pre_exec(|| {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            Ok(())
+        });
+    }
+}
+
+// The pager git itself would use. `cat` or an empty value turns paging off.
+fn pager_command() -> Option<String> {
+    let configured = env::var("GIT_PAGER")
+        .ok()
+        .or_else(|| capture_git(&[String::from("config"), String::from("core.pager")]).ok())
+        .or_else(|| env::var("PAGER").ok())
+        .unwrap_or_else(|| String::from("less"));
+    let configured = configured.trim();
+    if configured.is_empty() || configured == "cat" {
+        return None;
+    }
+    Some(configured.to_string())
+}
+
+fn start_pager(command: &str) -> Result<Child, String> {
+    let mut pager = Command::new("sh");
+    pager.arg("-c").arg(command).stdin(Stdio::piped());
+    restore_interrupt(&mut pager);
+    // The same defaults git gives `less` and `lv`, so colors survive
+    if env::var_os("LESS").is_none() {
+        pager.env("LESS", "FRX");
+    }
+    if env::var_os("LV").is_none() {
+        pager.env("LV", "-c");
+    }
+    pager
+        .spawn()
+        .map_err(|e| format!("Failed to run the pager '{}': {}", command, e))
+}
+
+// Where the output of the last stage ends up
+enum Screen {
+    Direct,
+    // The pager is started with the first output, so that commands that print
+    // nothing, or only progress on stderr, never open it
+    Waiting,
+    Paged(Child),
+}
+
+impl Screen {
+    fn new(options: &RunOptions, inherit: bool) -> Self {
+        if options.pager && !inherit && io::stdout().is_terminal() {
+            Screen::Waiting
+        } else {
+            Screen::Direct
+        }
+    }
+
+    // Like git, output is streamed into the pager as it arrives, and `less -F`
+    // quits by itself when it all fits on the screen
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            Screen::Direct => print_output(bytes),
+            Screen::Waiting => {
+                *self = match pager_command().map(|command| start_pager(&command)) {
+                    Some(Ok(pager)) => Screen::Paged(pager),
+                    Some(Err(e)) => {
+                        print_error(&e);
+                        Screen::Direct
+                    }
+                    None => Screen::Direct,
+                };
+                self.write(bytes);
+            }
+            Screen::Paged(pager) => {
+                if let Some(stdin) = pager.stdin.as_mut() {
+                    if stdin.write_all(bytes).is_err() {
+                        pager.stdin = None;
+                    }
+                }
+            }
+        }
+    }
+
+    // Whether the user quit the pager before the output ended
+    fn quit(&self) -> bool {
+        matches!(self, Screen::Paged(pager) if pager.stdin.is_none())
+    }
+
+    fn finish(self) {
+        if let Screen::Paged(mut pager) = self {
+            drop(pager.stdin.take());
+            if let Err(e) = pager.wait() {
+                print_error(&format!("Failed to wait for the pager: {}", e));
+            }
+        }
+    }
+}
+
+// Runs each stage with its stdout connected to the next one and prints the
+// output of the last process and the errors of all of them as they arrive.
+// Like in a shell, the exit code of the pipeline is that of its last process.
+pub fn run_pipeline(pipeline: Vec<PipelineStage>, options: &RunOptions) -> i32 {
+    let terminal = options.terminal;
+    if let Some(terminal) = terminal {
+        // Ensure the cursor is at the beginning of the line before printing
+        if let Err(e) = write!(io::stdout(), "\r\n\x1b[K") {
+            eprintln!("Failed to write to stdout: {}", e);
+        }
+        if let Err(e) = io::stdout().flush() {
+            eprintln!("Failed to flush stdout: {}", e);
+        }
+        hand_over_terminal(terminal);
+    }
+
+    // Without the REPL there is no raw mode to write around
+    let interactive = pipeline.iter().any(|stage| stage.interactive);
+    let job = current_job();
+    if let Some(job) = &job {
+        if interactive {
+            print_error("Commands that need the terminal cannot run in the background.");
+            return 1;
+        }
+        if job.interrupted() {
+            return INTERRUPTED;
+        }
+    }
+    let inherit = job.is_none() && (interactive || terminal.is_none());
+    let (sender, receiver) = mpsc::channel();
+    let mut stages = Vec::new();
+    let spawned = spawn_stages(pipeline, &mut stages, &sender, inherit, job.is_some());
+    // The channel closes once every reader thread has seen the end of its stream
+    drop(sender);
+    if let Err((status, e)) = spawned {
+        // Don't leave the stages that did start running unattended
+        for stage in &mut stages {
+            let _ = stage.child.kill();
+            let _ = stage.child.wait();
+        }
+        if let Some(terminal) = terminal {
+            take_back_terminal(terminal);
+        }
+        print_error(&e);
+        return status;
+    }
+
+    let mut pager_quit = false;
+    match &job {
+        Some(job) => {
+            job.set_groups(stages.iter().map(|stage| stage.child.id()).collect());
+            for output in receiver {
+                match output {
+                    Output::Stdout(bytes) | Output::Stderr(bytes) => job.write(&bytes),
+                }
+            }
+        }
+        None => {
+            let mut screen = Screen::new(options, inherit);
+            // Errors would draw over the pager, so they wait until it is closed
+            let mut held_errors = Vec::new();
+            for output in receiver {
+                match output {
+                    Output::Stdout(bytes) => screen.write(&bytes),
+                    Output::Stderr(bytes) if matches!(screen, Screen::Paged(_)) => {
+                        held_errors.extend_from_slice(&bytes)
+                    }
+                    Output::Stderr(bytes) => print_output(&bytes),
+                }
+                // Like with git's own pager, quitting it stops the command:
+                // the readers go away with the receiver, so the next write
+                // of the last stage gets SIGPIPE
+                if screen.quit() {
+                    pager_quit = true;
+                    break;
+                }
+            }
+            screen.finish();
+            print_output(&held_errors);
+        }
+    }
+
+    let mut status = 0;
+    let mut interrupted = false;
+    for stage in &mut stages {
+        status = match stage.child.wait() {
+            // Stopped because the pager was quit, which is not a failure
+            Ok(exit) if pager_quit && exit.signal() == Some(libc::SIGPIPE) => 0,
+            Ok(exit) => {
+                interrupted |= exit.signal() == Some(libc::SIGINT);
+                exit_code(exit)
+            }
+            Err(e) => {
+                print_error(&format!("Failed to wait for '{}': {}", stage.name, e));
+                1
+            }
+        };
+    }
+    if let Some(terminal) = terminal {
+        take_back_terminal(terminal);
+    }
+    if let Some(job) = &job {
+        job.set_groups(Vec::new());
+    }
+    if interrupted {
+        print_error("Command interrupted.");
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interactive(line: &str) -> bool {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        is_interactive(&args)
+    }
+
+    #[test]
+    fn detects_commands_that_need_the_terminal() {
+        assert!(interactive("commit"));
+        assert!(interactive("commit --amend"));
+        assert!(!interactive("commit -am fix"));
+        assert!(!interactive("commit --message=fix"));
+        assert!(interactive("commit -m fix -e"));
+        assert!(interactive("add -p"));
+        assert!(interactive("rebase -i HEAD~3"));
+        assert!(!interactive("rebase main"));
+        assert!(interactive("stash push -p"));
+        assert!(!interactive("stash show -p"));
+        assert!(!interactive("log -p"));
+        assert!(!interactive("checkout -- -p"));
+    }
+
+    #[test]
+    fn progress_is_requested_unless_chosen() {
+        let mut args = vec![String::from("fetch"), String::from("origin")];
+        request_progress(&mut args);
+        assert_eq!(args, ["fetch", "--progress", "origin"]);
+
+        let mut args = vec![String::from("push"), String::from("-q")];
+        request_progress(&mut args);
+        assert_eq!(args, ["push", "-q"]);
+    }
+
+    #[test]
+    fn pipelines_are_described_as_they_would_run() {
+        let args = [String::from("log"), String::from("--format=%h %s")];
+        let mut filter = Command::new("grep");
+        filter.arg("fix");
+        let pipeline = [
+            PipelineStage {
+                command: git_command(&args, true),
+                redirects: Vec::new(),
+                interactive: false,
+            },
+            PipelineStage {
+                command: filter,
+                redirects: vec![Redirect {
+                    stderr: false,
+                    append: true,
+                    path: String::from("notes.txt"),
+                }],
+                interactive: false,
+            },
+        ];
+        assert_eq!(
+            describe_pipeline(&pipeline),
+            "git -c color.ui=always --no-pager log '--format=%h %s' | grep fix >> notes.txt"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+// Pathname expansion for unquoted `*`, `?`, `[...]` and `**` in REPL
+// arguments. Patterns use `\` to escape a character that should match itself.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+enum PatternChar {
+    Literal(char),
+    // `*`: any run of characters
+    Star,
+    // `?`: any single character
+    Question,
+    // `[a-z_]` or `[!abc]`
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+pub fn has_magic(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn unescape(component: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+fn parse_component(component: &str) -> Vec<PatternChar> {
+    let chars: Vec<char> = component.chars().collect();
+    let mut pattern = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                pattern.push(PatternChar::Literal(chars[i + 1]));
+                i += 1;
+            }
+            '*' => pattern.push(PatternChar::Star),
+            '?' => pattern.push(PatternChar::Question),
+            '[' => match parse_class(&chars, i) {
+                Some((class, end)) => {
+                    pattern.push(class);
+                    i = end;
+                }
+                // A `[` without a closing `]` matches itself
+                None => pattern.push(PatternChar::Literal('[')),
+            },
+            c => pattern.push(PatternChar::Literal(c)),
+        }
+        i += 1;
+    }
+    pattern
+}
+
+// Parses the class opening at `chars[open]` and returns it with the index of its `]`
+fn parse_class(chars: &[char], open: usize) -> Option<(PatternChar, usize)> {
+    let mut i = open + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    // A `]` right after the opening bracket is part of the class
+    let first = i;
+    while i < chars.len() && (chars[i] != ']' || i == first) {
+        let mut start = chars[i];
+        if start == '\\' && i + 1 < chars.len() {
+            i += 1;
+            start = chars[i];
+        }
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&c| c != ']') {
+            ranges.push((start, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((start, start));
+            i += 1;
+        }
+    }
+
+    if i >= chars.len() {
+        return None;
+    }
+    Some((PatternChar::Class { negated, ranges }, i))
+}
+
+fn matches(pattern: &[PatternChar], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((PatternChar::Star, rest)) => {
+            (0..=name.len()).any(|skip| matches(rest, &name[skip..]))
+        }
+        Some((token, rest)) => {
+            let c = match name.first() {
+                Some(&c) => c,
+                None => return false,
+            };
+            let matched = match token {
+                PatternChar::Literal(literal) => c == *literal,
+                PatternChar::Question => true,
+                PatternChar::Class { negated, ranges } => {
+                    ranges.iter().any(|&(low, high)| low <= c && c <= high) != *negated
+                }
+                PatternChar::Star => unreachable!(),
+            };
+            matched && matches(rest, &name[1..])
+        }
+    }
+}
+
+fn read_dir_names(cwd: &Path, base: &Path) -> Vec<String> {
+    match fs::read_dir(cwd.join(base)) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// `**` matches `base` itself and everything below it, except hidden entries.
+// Files are only included when `**` is the last part of the pattern.
+fn collect_recursive(cwd: &Path, base: &Path, include_files: bool, paths: &mut Vec<PathBuf>) {
+    for name in read_dir_names(cwd, base) {
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = base.join(&name);
+        if cwd.join(&path).is_dir() {
+            paths.push(path.clone());
+            collect_recursive(cwd, &path, include_files, paths);
+        } else if include_files {
+            paths.push(path);
+        }
+    }
+}
+
+// Returns the paths matching `pattern`, sorted, or nothing when there is no
+// match. Relative patterns are matched against `cwd` and, like in a shell,
+// wildcards only match hidden names when the pattern starts with `.`.
+pub fn expand_glob(pattern: &str, cwd: &Path) -> Vec<String> {
+    let components: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+    let mut paths = vec![if pattern.starts_with('/') {
+        PathBuf::from("/")
+    } else {
+        PathBuf::new()
+    }];
+
+    for (index, component) in components.iter().enumerate() {
+        let last = index + 1 == components.len();
+        let mut next = Vec::new();
+
+        for base in &paths {
+            if *component == "**" {
+                if !base.as_os_str().is_empty() || !last {
+                    next.push(base.clone());
+                }
+                collect_recursive(cwd, base, last, &mut next);
+            } else if !has_magic(component) {
+                let path = base.join(unescape(component));
+                if cwd.join(&path).symlink_metadata().is_ok() {
+                    next.push(path);
+                }
+            } else {
+                let compiled = parse_component(component);
+                let show_hidden = component.starts_with('.');
+                for name in read_dir_names(cwd, base) {
+                    let chars: Vec<char> = name.chars().collect();
+                    if (show_hidden || !name.starts_with('.')) && matches(&compiled, &chars) {
+                        next.push(base.join(name));
+                    }
+                }
+            }
+        }
+        paths = next;
+    }
+
+    let mut matched: Vec<String> = paths
+        .into_iter()
+        .filter(|path| !path.as_os_str().is_empty())
+        .filter(|path| !pattern.ends_with('/') || cwd.join(path).is_dir())
+        .map(|path| {
+            let mut text = path.to_string_lossy().to_string();
+            if pattern.ends_with('/') {
+                text.push('/');
+            }
+            text
+        })
+        .collect();
+    matched.sort();
+    matched.dedup();
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, name: &str) -> bool {
+        let chars: Vec<char> = name.chars().collect();
+        matches(&parse_component(pattern), &chars)
+    }
+
+    #[test]
+    fn wildcards_match_names() {
+        assert!(is_match("*.rs", "main.rs"));
+        assert!(is_match("*.rs", ".rs"));
+        assert!(!is_match("*.rs", "main.rs.bak"));
+        assert!(is_match("ma?n.*", "main.rs"));
+        assert!(!is_match("ma?n", "man"));
+    }
+
+    #[test]
+    fn classes_match_ranges_and_negation() {
+        assert!(is_match("file[0-9].txt", "file7.txt"));
+        assert!(!is_match("file[!0-9].txt", "file7.txt"));
+        assert!(is_match("file[!0-9].txt", "fileA.txt"));
+        assert!(is_match("[]a]", "]"));
+        assert!(is_match("x[", "x["));
+    }
+
+    #[test]
+    fn escaped_characters_match_themselves() {
+        assert!(is_match(r"\*.rs", "*.rs"));
+        assert!(!is_match(r"\*.rs", "main.rs"));
+        assert!(!has_magic(r"notes\[1\].md"));
+        assert_eq!(unescape(r"notes\[1\].md"), "notes[1].md");
+    }
+}
@@ -0,0 +1,246 @@
+// Confirmation before git commands that throw work away, like `reset --hard`
+// or `push --force`. Which commands count is set with patterns in the
+// `dangerous { }` section of gitcmd.conf.
+
+use crate::config_io::GitCmdConfig;
+use crate::executor::capture_git_lines;
+use crate::input_handler::InputHandler;
+use crate::input_parser::quote_arg;
+use std::io;
+
+// Used while gitcmd.conf has no `dangerous` section
+const DEFAULT_PATTERNS: [&str; 6] = [
+    "push --force",
+    "push -f",
+    "reset --hard",
+    "clean -f",
+    "branch -D",
+    "checkout -- .",
+];
+
+pub fn dangerous_patterns(config: &GitCmdConfig) -> Vec<String> {
+    match &config.dangerous {
+        Some(patterns) => patterns.clone(),
+        None => DEFAULT_PATTERNS.iter().map(|p| p.to_string()).collect(),
+    }
+}
+
+// Long options with the short option they are the same as
+const LONG_OPTIONS: [(&str, &str); 2] = [("--force", "-f"), ("--delete", "-d")];
+
+// The words of a command with every option written one way: long options as
+// their short form, clusters like `-fdx` as single options, and `branch -D`
+// and `-M` as the `-d -f` and `-m -f` they stand for. Paths after `--` are
+// left alone.
+fn single_options(command: &str, words: &[&str]) -> Vec<String> {
+    let mut options = Vec::new();
+    let mut paths = false;
+    for &word in words {
+        let flags = word
+            .strip_prefix('-')
+            .filter(|flags| !paths && !flags.is_empty() && !flags.starts_with('-'));
+        if let Some(flags) = flags {
+            for flag in flags.chars() {
+                match (command, flag) {
+                    ("branch", 'D' | 'M') => {
+                        options.push(format!("-{}", flag.to_ascii_lowercase()));
+                        options.push(String::from("-f"));
+                    }
+                    _ => options.push(format!("-{}", flag)),
+                }
+            }
+            continue;
+        }
+        paths |= word == "--";
+        let short = LONG_OPTIONS
+            .iter()
+            .find(|(long, _)| !paths && *long == word)
+            .map(|(_, short)| *short);
+        options.push(short.unwrap_or(word).to_string());
+    }
+    options
+}
+
+// A pattern is a git command followed by words that must all appear in its
+// arguments, in any order. Options match however they are written, so
+// `clean -f` matches `clean -fdx` and `clean --force` too.
+fn matches_pattern(pattern: &str, args: &[String]) -> bool {
+    let words: Vec<&str> = pattern.split_whitespace().collect();
+    match (words.split_first(), args.split_first()) {
+        (Some((command, words)), Some((first, rest))) if command == first => {
+            let rest: Vec<&str> = rest.iter().map(String::as_str).collect();
+            let args = single_options(command, &rest);
+            single_options(command, words)
+                .iter()
+                .all(|word| args.contains(word))
+        }
+        _ => false,
+    }
+}
+
+// The first of `patterns` that `args` match
+pub fn find_dangerous<'a>(args: &[String], patterns: &'a [String]) -> Option<&'a str> {
+    patterns
+        .iter()
+        .find(|pattern| matches_pattern(pattern, args))
+        .map(String::as_str)
+}
+
+// A heading followed by what `args` printed, or nothing when they printed nothing
+fn section(heading: &str, args: &[&str]) -> Vec<String> {
+    let lines = capture_git_lines(args);
+    if lines.is_empty() {
+        return lines;
+    }
+    let mut section = vec![heading.to_string()];
+    section.extend(lines.into_iter().map(|line| format!("    {}", line)));
+    section
+}
+
+// Asks git what running `args` would lose: dropped commits, discarded changes
+// and deleted files. Git's own `--dry-run` is used where it has one.
+fn summarize(args: &[String]) -> Vec<String> {
+    let (command, rest) = match args.split_first() {
+        Some(split) => split,
+        None => return Vec::new(),
+    };
+    let rest: Vec<&str> = rest.iter().map(String::as_str).collect();
+    let operands: Vec<&str> = rest
+        .iter()
+        .copied()
+        .filter(|arg| !arg.starts_with('-'))
+        .collect();
+    // Paths are whatever follows `--`
+    let paths: Vec<&str> = rest
+        .iter()
+        .copied()
+        .skip_while(|arg| *arg != "--")
+        .collect();
+
+    match command.as_str() {
+        "reset" => {
+            let target = operands.first().copied().unwrap_or("HEAD");
+            let mut lines = section(
+                "Uncommitted changes that will be discarded:",
+                &["diff", "HEAD", "--stat"],
+            );
+            lines.extend(section(
+                "Commits that will no longer be on the branch:",
+                &["log", "--oneline", &format!("{}..HEAD", target)],
+            ));
+            lines
+        }
+        "clean" => section(
+            "Files that will be deleted:",
+            &[&["clean", "--dry-run"], &rest[..]].concat(),
+        ),
+        "push" => {
+            // The remote branch a force push overwrites, where it can be told
+            let remote_branch = match operands[..] {
+                [] => Some(String::from("@{push}")),
+                [remote, refspec, ..] => {
+                    let branch = refspec.rsplit(':').next().unwrap_or(refspec);
+                    Some(format!("{}/{}", remote, branch.trim_start_matches('+')))
+                }
+                _ => None,
+            };
+            let mut lines = section(
+                "What the push will do:",
+                &[&["push", "--dry-run"], &rest[..]].concat(),
+            );
+            if let Some(remote_branch) = remote_branch {
+                lines.extend(section(
+                    "Commits on the remote that will be overwritten:",
+                    &["log", "--oneline", &format!("HEAD..{}", remote_branch)],
+                ));
+            }
+            lines
+        }
+        "branch" => operands
+            .iter()
+            .flat_map(|branch| {
+                section(
+                    &format!("Commits on {} that are not in HEAD:", branch),
+                    &["log", "--oneline", &format!("HEAD..{}", branch)],
+                )
+            })
+            .collect(),
+        "checkout" | "restore" if !paths.is_empty() => section(
+            "Changes that will be discarded:",
+            &[&["diff", "--stat"], &paths[..]].concat(),
+        ),
+        _ => Vec::new(),
+    }
+}
+
+// Shows what `args` would lose and asks for an explicit `yes`. Returns
+// whether to run the command.
+pub fn confirm(args: &[String], pattern: &str) -> io::Result<bool> {
+    let mut input_handler = InputHandler {
+        stdout: io::stdout(),
+        last_ctrl_c: false,
+    };
+    let command: Vec<String> = args.iter().map(|arg| quote_arg(arg)).collect();
+    input_handler.write_line("")?;
+    input_handler.write_line(&format!(
+        "`git {}` matches the dangerous pattern `{}`.",
+        command.join(" "),
+        pattern
+    ))?;
+    for line in summarize(args) {
+        input_handler.write_line(&line)?;
+    }
+    let answer = input_handler.read_line("Type 'yes' to run it: ")?;
+    Ok(answer.is_some_and(|answer| answer.trim() == "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn patterns_match_words_in_any_order() {
+        assert!(matches_pattern(
+            "reset --hard",
+            &args("reset HEAD~1 --hard")
+        ));
+        assert!(matches_pattern("clean -f", &args("clean -fdx")));
+        assert!(matches_pattern("checkout -- .", &args("checkout -- .")));
+        assert!(!matches_pattern(
+            "push --force",
+            &args("push --force-with-lease")
+        ));
+        assert!(!matches_pattern("branch -D", &args("branch -d topic")));
+        assert!(!matches_pattern("reset --hard", &args("log reset --hard")));
+    }
+
+    #[test]
+    fn long_and_short_options_are_the_same() {
+        assert!(matches_pattern("clean -f", &args("clean --force -dx")));
+        assert!(matches_pattern(
+            "push --force",
+            &args("push -f origin main")
+        ));
+        assert!(matches_pattern("push -f", &args("push --force")));
+        assert!(matches_pattern("branch -D", &args("branch -d -f topic")));
+        assert!(matches_pattern(
+            "branch -D",
+            &args("branch --delete --force topic")
+        ));
+        assert!(matches_pattern("branch -D", &args("branch -df topic")));
+        assert!(matches_pattern(
+            "branch -D",
+            &args("branch --force -d topic")
+        ));
+        assert!(!matches_pattern(
+            "branch -D",
+            &args("branch --delete topic")
+        ));
+        assert!(!matches_pattern("checkout -- .", &args("checkout -f -- x")));
+        assert!(!matches_pattern("clean -f", &args("clean -n -- --force")));
+    }
+}
@@ -1,4 +1,4 @@
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 use termion::clear;
 use termion::cursor;
 use termion::event::Key;
@@ -31,6 +31,16 @@ impl<W: Write> InputHandler<W> {
         Ok(())
     }
 
+    // Waits for a single key press, e.g. to pick from a short list. Only one
+    // byte is read, so that nothing typed after it is lost.
+    pub fn read_key(&mut self) -> io::Result<Option<char>> {
+        let mut byte = [0];
+        match io::stdin().read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(char::from(byte[0]))),
+        }
+    }
+
     pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
         self.write_str(prompt)?;
 
@@ -1,99 +1,1369 @@
+use crate::alias::{describe_alias, expand_aliases};
 use crate::config::setup_git_conf_profile;
-use regex::Regex;
-use std::io::{self, Write};
-use std::process::{Command, Stdio};
-
-pub fn parse_and_execute_line(line: String) -> bool {
-    // Split the input line by && operator and trim whitespace
-    let commands: Vec<&str> = line.split("&&").map(|s| s.trim()).collect();
-
-    for cmd in commands {
-        let mut line = cmd.to_string();
-        // Check if the line starts with "git "
-        if let Some(trimmed) = line.strip_prefix("git ") {
-            println!("Note: gitcmd does not require the 'git' prefix.");
-            line = trimmed.to_string();
-        }
-
-        // Regex to match either:
-        // - Non-whitespace characters (word arguments)
-        // - Or a quoted string (arguments inside double quotes)
-        let re = Regex::new(r#""([^"]*)"|\S+"#).expect("FATAL: Failed to execute regex operation.");
-
-        // Find all the matches of words or quoted strings
-        let args: Vec<String> = re
-            .find_iter(&line)
-            .map(|mat| mat.as_str().trim_matches('"').to_string())
-            .collect();
-
-        // If there is no command, skip to next command
-        if args.is_empty() {
-            println!("\r\n");
-            continue;
-        } else if args.len() == 1 && args[0] == "setup" {
-            setup_git_conf_profile();
-            continue;
+use crate::config_io::GitCmdAlias;
+use crate::directory::DIRECTORY_BUILTINS;
+use crate::executor::{
+    capture_git, describe_pipeline, git_command, in_background_job, is_interactive, last_status,
+    print_error, print_line, request_progress, run_pipeline, set_last_status, shell_alias_command,
+    shell_command, working_dir, PipelineStage, RunOptions, INTERRUPTED,
+};
+use crate::glob::expand_glob;
+use crate::guard::{confirm, find_dangerous};
+use crate::jobs::Jobs;
+use dirs::home_dir;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::mem;
+use std::process::Command;
+use std::slice;
+
+// Words being collected by `tokenize`
+#[derive(Default)]
+struct Words {
+    words: Vec<String>,
+    word: String,
+    // Tracks whether a word was started, so that `""` still yields an empty argument
+    in_word: bool,
+    // The word as a glob pattern, with quoted wildcard characters escaped
+    pattern: String,
+    has_glob: bool,
+}
+
+impl Words {
+    fn push(&mut self, c: char) {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            self.pattern.push('\\');
         }
+        self.pattern.push(c);
+        self.word.push(c);
+        self.in_word = true;
+    }
 
-        // Create the command and add the common arguments
-        let mut command = Command::new("git");
-        command.arg("-c").arg("color.ui=always").arg("--no-pager");
+    fn push_str(&mut self, text: &str) {
+        for c in text.chars() {
+            self.push(c);
+        }
+        self.in_word = true;
+    }
 
-        // Add each argument from the line
-        for arg in args {
-            command.arg(arg);
+    // An unquoted wildcard, which makes the word a pattern
+    fn push_glob(&mut self, c: char) {
+        self.push_unescaped(c);
+        self.has_glob = true;
+    }
+
+    // An unquoted character that keeps its meaning in the pattern, like the
+    // `]` that closes a class, without being a wildcard by itself
+    fn push_unescaped(&mut self, c: char) {
+        self.pattern.push(c);
+        self.word.push(c);
+        self.in_word = true;
+    }
+
+    fn end_word(&mut self) {
+        if !self.in_word {
+            return;
+        }
+        let word = std::mem::take(&mut self.word);
+        let pattern = std::mem::take(&mut self.pattern);
+        let matches = if self.has_glob {
+            expand_glob(&pattern, &working_dir())
+        } else {
+            Vec::new()
+        };
+        // A pattern without matches is passed on unchanged
+        if matches.is_empty() {
+            self.words.push(word);
+        } else {
+            self.words.extend(matches);
+        }
+        self.in_word = false;
+        self.has_glob = false;
+    }
+
+    // Unquoted expansions are split into separate words on whitespace
+    fn push_split(&mut self, text: &str) {
+        for c in text.chars() {
+            if c.is_whitespace() {
+                self.end_word();
+            } else {
+                self.push(c);
+            }
         }
+    }
+}
 
-        // Execute the git command
-        let output = command
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output();
+// Splits a command into words following POSIX quoting rules:
+// - 'single quotes' keep everything literally
+// - "double quotes" keep everything except \" \\ \$ and \` escapes, and
+//   expand `$VAR`, `${VAR}` and `$(...)` without splitting the result
+// - a backslash outside of quotes escapes the next character
+// - quoted and unquoted parts next to each other form one word
+// - outside of quotes, expansions are split on whitespace, a leading `~`
+//   becomes the home directory and words with wildcards are matched against
+//   the working tree
+pub fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut words = Words::default();
+    let mut i = 0;
 
-        match output {
-            Ok(output) => {
-                // Ensure the cursor is at the beginning of the line before printing
-                if let Err(e) = write!(io::stdout(), "\r\n\x1b[K") {
-                    eprintln!("Failed to write to stdout: {}", e);
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        match c {
+            c if c.is_whitespace() => words.end_word(),
+            '\'' => {
+                words.in_word = true;
+                loop {
+                    match chars.get(i) {
+                        Some('\'') => break,
+                        Some(&ch) => words.push(ch),
+                        None => return Err(String::from("Unterminated single quote.")),
+                    }
+                    i += 1;
                 }
-
-                if !output.status.success() {
-                    // If any command fails, print the error and stop execution
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    for line in stderr.lines() {
-                        if let Err(e) = writeln!(io::stdout(), "\r\x1b[K{}", line) {
-                            eprintln!("Failed to write to stdout: {}", e);
-                        }
+                i += 1;
+            }
+            '"' => {
+                words.in_word = true;
+                loop {
+                    match chars.get(i) {
+                        Some('"') => break,
+                        Some('\\') => match chars.get(i + 1) {
+                            Some(&next) if matches!(next, '"' | '\\' | '$' | '`') => {
+                                words.push(next);
+                                i += 1;
+                            }
+                            _ => words.push('\\'),
+                        },
+                        Some('$') => match expand_dollar(&chars, &mut i)? {
+                            Some(value) => {
+                                words.push_str(&value);
+                                continue;
+                            }
+                            None => words.push('$'),
+                        },
+                        Some(&ch) => words.push(ch),
+                        None => return Err(String::from("Unterminated double quote.")),
                     }
-                    if let Err(e) = io::stdout().flush() {
-                        eprintln!("Failed to flush stdout: {}", e);
+                    i += 1;
+                }
+                i += 1;
+            }
+            '\\' => {
+                // A trailing backslash has nothing to escape and is kept as is
+                words.push(chars.get(i).copied().unwrap_or('\\'));
+                i += 1;
+            }
+            '$' => {
+                let mut end = i - 1;
+                match expand_dollar(&chars, &mut end)? {
+                    Some(value) => {
+                        words.push_split(&value);
+                        i = end;
                     }
-                    // Return false to indicate failure
-                    return false;
+                    None => words.push('$'),
                 }
+            }
+            '~' if !words.in_word
+                && chars.get(i).is_none_or(|&c| c == '/' || c.is_whitespace()) =>
+            {
+                let home = home_dir().ok_or("Unable to determine home directory")?;
+                words.push_str(&home.to_string_lossy());
+            }
+            '*' | '?' | '[' => words.push_glob(c),
+            ']' => words.push_unescaped(c),
+            _ => words.push(c),
+        }
+    }
+    words.end_word();
+
+    Ok(words.words)
+}
+
+// Expands the `$VAR`, `${VAR}`, `${VAR:-default}`, `$?` or `$(...)` that starts at
+// `chars[*i]` and moves `i` past it. Returns `None`, without moving, when the
+// `$` does not start an expansion and should be kept as is.
+fn expand_dollar(chars: &[char], i: &mut usize) -> Result<Option<String>, String> {
+    let start = *i + 1;
+    match chars.get(start) {
+        Some('(') => {
+            let end = substitution_end(chars, start)
+                .ok_or_else(|| String::from("Unterminated command substitution."))?;
+            let inner: String = chars[start + 1..end].iter().collect();
+            *i = end + 1;
+            run_substitution(&inner).map(Some)
+        }
+        Some('?') => {
+            *i = start + 1;
+            Ok(Some(last_status().to_string()))
+        }
+        Some('{') => {
+            let end = (start..chars.len())
+                .find(|&j| chars[j] == '}')
+                .ok_or_else(|| String::from("Unterminated '${' expansion."))?;
+            let inner: String = chars[start + 1..end].iter().collect();
+            let (name, default) = match inner.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (inner.as_str(), None),
+            };
+            if !is_variable_name(name) {
+                return Err(format!("Bad substitution: '${{{}}}'.", inner));
+            }
+            *i = end + 1;
+            let value = env::var(name).unwrap_or_default();
+            match default {
+                Some(default) if value.is_empty() => Ok(Some(default.to_string())),
+                _ => Ok(Some(value)),
+            }
+        }
+        Some(&c) if c == '_' || c.is_ascii_alphabetic() => {
+            let end = (start..chars.len())
+                .find(|&j| !(chars[j] == '_' || chars[j].is_ascii_alphanumeric()))
+                .unwrap_or(chars.len());
+            let name: String = chars[start..end].iter().collect();
+            *i = end;
+            Ok(Some(env::var(name).unwrap_or_default()))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+// Index of the `)` that closes the `(` at `open`, skipping over quotes and
+// nested substitutions
+fn substitution_end(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut i = open;
 
-                // Print stdout to terminal including colors
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                for line in stdout.lines() {
-                    if let Err(e) = writeln!(io::stdout(), "\r\x1b[K{}", line) {
-                        eprintln!("Failed to write to stdout: {}", e);
+    while i < chars.len() {
+        let c = chars[i];
+        match quote {
+            Some('\'') if c == '\'' => quote = None,
+            Some('\'') => {}
+            Some(_) if c == '\\' => i += 1,
+            Some(_) if c == '"' => quote = None,
+            Some(_) if c == '$' && chars.get(i + 1) == Some(&'(') => {
+                i = substitution_end(chars, i + 1)?;
+            }
+            Some(_) => {}
+            None => match c {
+                '\\' => i += 1,
+                '\'' | '"' => quote = Some(c),
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(i);
                     }
                 }
+                _ => {}
+            },
+        }
+        i += 1;
+    }
+    None
+}
+
+// `$(...)` runs a git command, like the rest of the line, and is replaced by
+// its output without the trailing newlines
+fn run_substitution(inner: &str) -> Result<String, String> {
+    let mut args = tokenize(inner)?;
+    if args.first().map(String::as_str) == Some("git") {
+        args.remove(0);
+    }
+    if args.is_empty() {
+        return Ok(String::new());
+    }
+    capture_git(&args).map_err(|e| {
+        format!(
+            "Command substitution `$(git {})` failed: {}",
+            args.join(" "),
+            e
+        )
+    })
+}
 
-                if let Err(e) = io::stdout().flush() {
-                    eprintln!("Failed to flush stdout: {}", e);
+// Inverse of `tokenize` for a single argument, used when splicing arguments
+// back into a command line.
+pub fn quote_arg(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+^".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+// Splits a command into words as typed, with quotes, escapes and expansions
+// left in place for `tokenize` to handle when the command runs
+pub fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote: Option<char> = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == '$' && next == Some('(') && quote != Some('\'') {
+            let end = substitution_end(&chars, i + 1)
+                .ok_or_else(|| String::from("Unterminated command substitution."))?;
+            word.extend(&chars[i..=end]);
+            i = end + 1;
+            continue;
+        }
+        match quote {
+            None if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(mem::take(&mut word));
                 }
             }
-            Err(e) => {
-                // Handle the error if the command fails to execute
-                if let Err(write_err) =
-                    writeln!(io::stdout(), "\r\x1b[KFailed to run the command: {}", e)
-                {
-                    eprintln!("Failed to write to stdout: {}", write_err);
+            Some(q) if c == q => {
+                word.push(c);
+                quote = None;
+            }
+            None if c == '\'' || c == '"' => {
+                word.push(c);
+                quote = Some(c);
+            }
+            None | Some('"') if c == '\\' => {
+                word.push(c);
+                word.extend(next);
+                i += 1;
+            }
+            _ => word.push(c),
+        }
+        i += 1;
+    }
+    match quote {
+        Some('"') => return Err(String::from("Unterminated double quote.")),
+        Some(_) => return Err(String::from("Unterminated single quote.")),
+        None if !word.is_empty() => words.push(word),
+        None => {}
+    }
+    Ok(words)
+}
+
+// Rewrites a word from `split_words` so that it can be placed inside double
+// quotes and still mean the same, minus word splitting and globbing: quoted
+// text stays literal and expansions stay expansions
+pub fn requote_in_double_quotes(word: &str) -> String {
+    let chars: Vec<char> = word.chars().collect();
+    let mut requoted = String::new();
+    let mut quote: Option<char> = None;
+    let mut i = 0;
+    let literal = |requoted: &mut String, c: char| {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            requoted.push('\\');
+        }
+        requoted.push(c);
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == '$' && next == Some('(') && quote != Some('\'') {
+            if let Some(end) = substitution_end(&chars, i + 1) {
+                requoted.extend(&chars[i..=end]);
+                i = end + 1;
+                continue;
+            }
+        }
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some('\''), _) => literal(&mut requoted, c),
+            (Some(_), '\\') => {
+                requoted.push(c);
+                requoted.extend(next);
+                i += 1;
+            }
+            (Some(_), _) => requoted.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '\\') => {
+                literal(&mut requoted, next.unwrap_or(c));
+                i += 1;
+            }
+            (None, '$') => requoted.push(c),
+            // `~` is not expanded inside quotes, but `$HOME` is
+            (None, '~') if i == 0 && next.is_none_or(|next| next == '/') => {
+                requoted.push_str("${HOME}")
+            }
+            (None, _) => literal(&mut requoted, c),
+        }
+        i += 1;
+    }
+    requoted
+}
+
+// Where a `>`, `>>`, `2>` or `2>>` sends a command's output
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub stderr: bool,
+    pub append: bool,
+    pub path: String,
+}
+
+// Removes the redirections outside of quotes from a command's text, returning
+// the remaining text and each operator with its target as typed, in the order
+// they were written
+fn take_redirects(text: &str) -> Result<(String, Vec<(String, String)>), String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut remaining = String::new();
+    let mut redirects = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '$' && chars.get(i + 1) == Some(&'(') && quote != Some('\'') {
+            let end = substitution_end(&chars, i + 1)
+                .ok_or_else(|| String::from("Unterminated command substitution."))?;
+            remaining.extend(&chars[i..=end]);
+            i = end + 1;
+            continue;
+        }
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            } else if c == '\\' && q == '"' && i + 1 < chars.len() {
+                remaining.push(c);
+                i += 1;
+            }
+            remaining.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        if c == '\\' {
+            remaining.extend(chars.get(i..i + 2).unwrap_or(&chars[i..]));
+            i += 2;
+            continue;
+        }
+
+        if c == '&' && chars.get(i + 1) == Some(&'>') {
+            let operator = if chars.get(i + 2) == Some(&'>') {
+                "&>>"
+            } else {
+                "&>"
+            };
+            return Err(format!(
+                "Redirecting both outputs at once (`{}`) is not supported; use `> file 2> other-file`.",
+                operator
+            ));
+        }
+        let word_start = i == 0 || chars[i - 1].is_whitespace();
+        let stderr = c == '2' && word_start && chars.get(i + 1) == Some(&'>');
+        if c != '>' && !stderr {
+            if c == '"' || c == '\'' {
+                quote = Some(c);
+            }
+            remaining.push(c);
+            i += 1;
+            continue;
+        }
+
+        let operator_start = i;
+        i += if stderr { 2 } else { 1 };
+        let append = chars.get(i) == Some(&'>');
+        if append {
+            i += 1;
+        }
+        let operator: String = chars[operator_start..i].iter().collect();
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+
+        // The target runs to the next unquoted whitespace
+        let mut target = String::new();
+        let mut target_quote: Option<char> = None;
+        while i < chars.len() {
+            let t = chars[i];
+            match target_quote {
+                Some(q) if t == q => target_quote = None,
+                None if t.is_whitespace() => break,
+                None if t == '"' || t == '\'' => target_quote = Some(t),
+                _ => {}
+            }
+            target.push(t);
+            i += 1;
+        }
+        redirects.push((operator, target));
+        remaining.push(' ');
+    }
+
+    Ok((remaining.trim().to_string(), redirects))
+}
+
+// Removes the redirections outside of quotes from a command's text, returning
+// the remaining text and the redirections in the order they were written
+pub fn split_redirects(text: &str) -> Result<(String, Vec<Redirect>), String> {
+    let (remaining, typed) = take_redirects(text)?;
+    let mut redirects = Vec::new();
+    for (operator, target) in typed {
+        let path = tokenize(&target)?.concat();
+        if path.is_empty() {
+            return Err(format!(
+                "Syntax error: missing file name after `{}`.",
+                operator
+            ));
+        }
+        if path.starts_with('&') {
+            return Err(format!(
+                "Redirecting to a file descriptor (`{}{}`) is not supported.",
+                operator, path
+            ));
+        }
+        redirects.push(Redirect {
+            stderr: operator.starts_with('2'),
+            append: operator.ends_with(">>"),
+            path,
+        });
+    }
+    Ok((remaining, redirects))
+}
+
+// Like `split_redirects`, but with the redirections left as typed, joined
+// into one piece of text
+pub fn strip_redirects(text: &str) -> Result<(String, String), String> {
+    let (remaining, typed) = take_redirects(text)?;
+    let typed: Vec<String> = typed
+        .iter()
+        .map(|(operator, target)| format!("{} {}", operator, target))
+        .collect();
+    Ok((remaining, typed.join(" ")))
+}
+
+// How a command in a list is joined to the one before it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListOp {
+    // `;`, or the first command of a list: always runs
+    Seq,
+    // `&&`: runs only if the previous command succeeded
+    And,
+    // `||`: runs only if the previous command failed
+    Or,
+}
+
+impl ListOp {
+    // Whether a command joined with this operator runs after one that exited
+    // with `status`
+    fn runs_after(self, status: i32) -> bool {
+        match self {
+            ListOp::Seq => true,
+            ListOp::And => status == 0,
+            ListOp::Or => status != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ListNode {
+    // Arguments for `git`, still as typed text
+    Git(String),
+    // Body of a `!` alias, run through the user's shell like git does
+    Shell {
+        alias: String,
+        script: String,
+        // The words typed after the alias name, as typed
+        args: String,
+    },
+    // A `!cmd` typed in the REPL, run through the user's shell where gitcmd is
+    Local(String),
+    // A parenthesized group, or the body of a compound alias
+    Group(Vec<ListItem>),
+}
+
+#[derive(Debug, Clone)]
+pub struct ListItem {
+    pub op: ListOp,
+    pub node: ListNode,
+    // Local programs that the command's output is piped through, as typed
+    pub filters: Vec<String>,
+    // Ended with `&`, to run as a background job
+    pub background: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum ListToken {
+    Text(String),
+    And,
+    Or,
+    Pipe,
+    Semi,
+    Background,
+    Open,
+    Close,
+}
+
+impl ListToken {
+    fn as_str(&self) -> &str {
+        match self {
+            ListToken::Text(text) => text,
+            ListToken::And => "&&",
+            ListToken::Or => "||",
+            ListToken::Pipe => "|",
+            ListToken::Semi => ";",
+            ListToken::Background => "&",
+            ListToken::Open => "(",
+            ListToken::Close => ")",
+        }
+    }
+}
+
+// Splits a line on the list operators that are outside of quotes. The text
+// between operators is kept as typed, quotes included.
+fn lex_list(line: &str) -> Result<Vec<ListToken>, String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut quote: Option<char> = None;
+    let mut i = 0;
+
+    let flush = |text: &mut String, tokens: &mut Vec<ListToken>| {
+        if !text.trim().is_empty() {
+            tokens.push(ListToken::Text(text.trim().to_string()));
+        }
+        text.clear();
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        // Operators inside `$(...)` belong to the substituted command
+        if c == '$' && next == Some('(') && quote != Some('\'') {
+            let end = substitution_end(&chars, i + 1)
+                .ok_or_else(|| String::from("Unterminated command substitution."))?;
+            text.extend(&chars[i..=end]);
+            i = end + 1;
+            continue;
+        }
+
+        if let Some(q) = quote {
+            text.push(c);
+            if c == '\\' && q == '"' {
+                if let Some(escaped) = next {
+                    text.push(escaped);
+                    i += 1;
                 }
-                return false;
+            } else if c == q {
+                quote = None;
+            }
+            i += 1;
+            continue;
+        }
+
+        let operator = match (c, next) {
+            ('&', Some('&')) => Some((ListToken::And, 2)),
+            // `>&` and `&>` are redirections, not a background job
+            ('&', _) if next != Some('>') && !(i > 0 && chars[i - 1] == '>') => {
+                Some((ListToken::Background, 1))
+            }
+            ('|', Some('|')) => Some((ListToken::Or, 2)),
+            ('|', _) => Some((ListToken::Pipe, 1)),
+            (';', _) => Some((ListToken::Semi, 1)),
+            ('(', _) => Some((ListToken::Open, 1)),
+            (')', _) => Some((ListToken::Close, 1)),
+            _ => None,
+        };
+        if let Some((token, len)) = operator {
+            flush(&mut text, &mut tokens);
+            tokens.push(token);
+            i += len;
+            continue;
+        }
+
+        text.push(c);
+        if c == '\'' || c == '"' {
+            quote = Some(c);
+        } else if c == '\\' {
+            if let Some(escaped) = next {
+                text.push(escaped);
+                i += 1;
+            }
+        }
+        i += 1;
+    }
+    match quote {
+        Some('"') => return Err(String::from("Unterminated double quote.")),
+        Some(_) => return Err(String::from("Unterminated single quote.")),
+        None => flush(&mut text, &mut tokens),
+    }
+
+    Ok(tokens)
+}
+
+// Parses `&&`, `||`, `;` and `( ... )` into a command list. Like a POSIX
+// shell, the operators are left-associative and have equal precedence, and
+// `|` binds tighter than all of them.
+pub fn parse_command_list(line: &str) -> Result<Vec<ListItem>, String> {
+    let tokens = lex_list(line)?;
+    let mut pos = 0;
+    let items = parse_list(&tokens, &mut pos, 0)?;
+    if let Some(token) = tokens.get(pos) {
+        return Err(syntax_error(token.as_str()));
+    }
+    Ok(items)
+}
+
+fn parse_list(
+    tokens: &[ListToken],
+    pos: &mut usize,
+    depth: usize,
+) -> Result<Vec<ListItem>, String> {
+    let mut items = Vec::new();
+    let mut op = ListOp::Seq;
+    let mut expect_command = true;
+    // Where the commands that a `&` would send to the background start
+    let mut segment = 0;
+
+    while let Some(token) = tokens.get(*pos) {
+        match token {
+            ListToken::Text(text) if expect_command => {
+                let node = match text.strip_prefix('!') {
+                    Some(script) if script.trim().is_empty() => {
+                        return Err(String::from("Syntax error: empty command after `!`."))
+                    }
+                    Some(script) => ListNode::Local(script.trim().to_string()),
+                    None => ListNode::Git(text.clone()),
+                };
+                items.push(ListItem {
+                    op,
+                    node,
+                    filters: Vec::new(),
+                    background: false,
+                });
+                expect_command = false;
+            }
+            ListToken::Open if expect_command => {
+                *pos += 1;
+                let group = parse_list(tokens, pos, depth + 1)?;
+                if tokens.get(*pos) != Some(&ListToken::Close) {
+                    return Err(String::from("Syntax error: missing ')'."));
+                }
+                items.push(ListItem {
+                    op,
+                    node: ListNode::Group(group),
+                    filters: Vec::new(),
+                    background: false,
+                });
+                expect_command = false;
+            }
+            ListToken::Pipe if !expect_command => {
+                *pos += 1;
+                let filter = match tokens.get(*pos) {
+                    Some(ListToken::Text(text)) => text.clone(),
+                    Some(token) => return Err(syntax_error(token.as_str())),
+                    None => return Err(String::from("Syntax error: unexpected end of line.")),
+                };
+                if let Some(item) = items.last_mut() {
+                    item.filters.push(filter);
+                }
+            }
+            ListToken::Close if depth > 0 && !expect_command => break,
+            ListToken::And | ListToken::Or | ListToken::Semi if !expect_command => {
+                op = match token {
+                    ListToken::And => ListOp::And,
+                    ListToken::Or => ListOp::Or,
+                    _ => ListOp::Seq,
+                };
+                if op == ListOp::Seq {
+                    segment = items.len();
+                }
+                expect_command = true;
+            }
+            // Like in a shell, `&` sends everything since the last `;` or `&`
+            // to the background, so `fetch && rebase &` runs both there
+            ListToken::Background if !expect_command => {
+                let mut job = items.split_off(segment);
+                let item = if job.len() == 1 {
+                    let mut item = job.remove(0);
+                    item.background = true;
+                    item
+                } else {
+                    ListItem {
+                        op: mem::replace(&mut job[0].op, ListOp::Seq),
+                        node: ListNode::Group(job),
+                        filters: Vec::new(),
+                        background: true,
+                    }
+                };
+                items.push(item);
+                segment = items.len();
+                op = ListOp::Seq;
+                expect_command = true;
+            }
+            // A trailing `;` is allowed, as in `status;`
+            ListToken::Close if depth > 0 && op == ListOp::Seq && !items.is_empty() => break,
+            _ => return Err(syntax_error(token.as_str())),
+        }
+        *pos += 1;
+    }
+
+    if items.is_empty() {
+        return Err(String::from("Syntax error: empty command."));
+    }
+    if expect_command && op != ListOp::Seq {
+        return Err(String::from("Syntax error: unexpected end of line."));
+    }
+    Ok(items)
+}
+
+fn syntax_error(token: &str) -> String {
+    format!("Syntax error near unexpected token `{}`.", token)
+}
+
+// Renders a command list back into one line, e.g. for `which`
+pub fn display_list(items: &[ListItem]) -> String {
+    let mut line = String::new();
+    for item in items {
+        match item.op {
+            ListOp::Seq if line.is_empty() || line.ends_with("& ") => {}
+            ListOp::Seq => line.push_str("; "),
+            ListOp::And => line.push_str(" && "),
+            ListOp::Or => line.push_str(" || "),
+        }
+        match &item.node {
+            ListNode::Git(text) => line.push_str(&format!("git {}", text)),
+            ListNode::Shell { script, args, .. } if args.is_empty() => {
+                line.push_str(&format!("!{}", script))
+            }
+            ListNode::Shell { script, args, .. } => line.push_str(&format!("!{} {}", script, args)),
+            ListNode::Local(script) => line.push_str(&format!("!{}", script)),
+            ListNode::Group(group) => line.push_str(&format!("({})", display_list(group))),
+        }
+        for filter in &item.filters {
+            line.push_str(&format!(" | {}", filter));
+        }
+        if item.background {
+            line.push_str(" & ");
+        }
+    }
+    line.trim_end().to_string()
+}
+
+// Runs a line and returns its exit code, which is also kept for `$?`. Errors
+// that gitcmd reports itself count as 1.
+pub fn parse_and_execute_line(line: String, aliases: &[GitCmdAlias], options: &RunOptions) -> i32 {
+    // Expand aliases first, since an alias body may itself be a command list
+    let status = match expand_aliases(&line, aliases) {
+        Ok(commands) if options.dry_run => print_dry_run(&commands, aliases, options, 0),
+        Ok(commands) => execute_list(&commands, aliases, options),
+        Err(e) => {
+            print_error(&e);
+            1
+        }
+    };
+    set_last_status(status);
+    status
+}
+
+// Runs each command whose operator accepts the status of the last command
+// that ran, and returns the status of the last one run
+fn execute_list(items: &[ListItem], aliases: &[GitCmdAlias], options: &RunOptions) -> i32 {
+    let mut status = 0;
+    for item in items {
+        if item.op.runs_after(status) {
+            status = match options.jobs {
+                Some(jobs) if item.background => start_job(item, aliases, options, jobs),
+                _ => execute_item(item, aliases, options),
+            };
+            set_last_status(status);
+        }
+        // Ctrl-C stops the whole line, not just the command that was running
+        if status == INTERRUPTED {
+            break;
+        }
+    }
+    status
+}
+
+// Prints the commands a list would run, one per line, instead of running them.
+// Which of them run depends on exit codes, so every one is shown along with
+// the operator in front of it. Returns 1 if any of them could not be built.
+fn print_dry_run(
+    items: &[ListItem],
+    aliases: &[GitCmdAlias],
+    options: &RunOptions,
+    depth: usize,
+) -> i32 {
+    let indent = "  ".repeat(depth);
+    let mut status = 0;
+    for item in items {
+        let op = match item.op {
+            ListOp::Seq => "",
+            ListOp::And => "&& ",
+            ListOp::Or => "|| ",
+        };
+        let background = if item.background { " &" } else { "" };
+        match &item.node {
+            ListNode::Group(group) if item.filters.is_empty() => {
+                print_line(&format!("{}{}(", indent, op));
+                status = status.max(print_dry_run(group, aliases, options, depth + 1));
+                print_line(&format!("{}){}", indent, background));
+            }
+            _ => match build_pipeline(item, aliases, options) {
+                Ok(Some(stages)) => print_line(&format!(
+                    "{}{}{}{}",
+                    indent,
+                    op,
+                    describe_pipeline(&stages),
+                    background
+                )),
+                Ok(None) => {}
+                Err(e) => {
+                    print_error(&e);
+                    status = 1;
+                }
+            },
+        }
+    }
+    status
+}
+
+// Starts `item` as a background job. Starting it is what succeeds, like in a shell.
+fn start_job(item: &ListItem, aliases: &[GitCmdAlias], options: &RunOptions, jobs: &Jobs) -> i32 {
+    let mut job = item.clone();
+    job.background = false;
+    let line = display_list(slice::from_ref(&job));
+    let aliases = aliases.to_vec();
+    let guard = options.guard.to_vec();
+    let id = jobs.start(line.clone(), move |options| {
+        let options = RunOptions {
+            guard: &guard,
+            ..*options
+        };
+        execute_item(&job, &aliases, &options)
+    });
+    print_error(&format!("[{}] {}", id, line));
+    0
+}
+
+fn execute_item(item: &ListItem, aliases: &[GitCmdAlias], options: &RunOptions) -> i32 {
+    if let ListNode::Group(items) = &item.node {
+        if item.filters.is_empty() {
+            return execute_list(items, aliases, options);
+        }
+    }
+
+    match build_pipeline(item, aliases, options) {
+        Ok(Some(stages)) => run_pipeline(stages, options),
+        Ok(None) => 0,
+        Err(e) => {
+            print_error(&e);
+            1
+        }
+    }
+}
+
+// Turns one list item into the processes to run. Builtins are run right
+// away, in which case there is nothing left to spawn. For a dry run they are
+// only named.
+fn build_pipeline(
+    item: &ListItem,
+    aliases: &[GitCmdAlias],
+    options: &RunOptions,
+) -> Result<Option<Vec<PipelineStage>>, String> {
+    let mut filters = Vec::new();
+    for filter in &item.filters {
+        let (text, redirects) = split_redirects(filter)?;
+        let args = tokenize(&text)?;
+        let (program, args) = args
+            .split_first()
+            .ok_or_else(|| String::from("Syntax error: empty command after `|`."))?;
+        let mut command = Command::new(program);
+        command.args(args);
+        filters.push(PipelineStage {
+            command,
+            redirects,
+            interactive: false,
+        });
+    }
+
+    let (mut line, redirects) = match &item.node {
+        ListNode::Git(line) => split_redirects(line)?,
+        ListNode::Shell {
+            alias,
+            script,
+            args,
+        } => {
+            let (args, redirects) = split_redirects(args)?;
+            let command = shell_alias_command(alias, script, &tokenize(&args)?);
+            // Like in git, a `!` alias may be anything, so it gets the terminal,
+            // unless it runs as a background job
+            let mut stages = vec![PipelineStage {
+                command,
+                redirects,
+                interactive: !in_background_job(),
+            }];
+            stages.extend(filters);
+            return Ok(Some(stages));
+        }
+        // The shell does its own quoting, expansion and redirection
+        ListNode::Local(script) => {
+            let mut stages = vec![PipelineStage {
+                command: shell_command(script),
+                redirects: Vec::new(),
+                interactive: !in_background_job(),
+            }];
+            stages.extend(filters);
+            return Ok(Some(stages));
+        }
+        ListNode::Group(_) => {
+            return Err(String::from(
+                "Cannot pipe the output of a group or a compound alias.",
+            ))
+        }
+    };
+
+    // Check if the line starts with "git "
+    if let Some(trimmed) = line.strip_prefix("git ") {
+        print_error("Note: gitcmd does not require the 'git' prefix.");
+        line = trimmed.to_string();
+    }
+
+    let mut args = tokenize(&line)?;
+
+    // If there is no command, skip to next command
+    if args.is_empty() {
+        println!("\r\n");
+        return Ok(None);
+    }
+    // `cd` and the like change the REPL's own directory, which a list, a pipe
+    // or a job can't do in the middle of running
+    if DIRECTORY_BUILTINS.contains(&args[0].as_str()) {
+        return Err(format!(
+            "`{}` only works on a line of its own in the terminal, not combined with `&&`, `||`, `;`, `|` or `&`.",
+            args[0]
+        ));
+    }
+    let builtin =
+        (args.len() == 1 && args[0] == "setup") || (args.len() == 2 && args[0] == "which");
+    if builtin && options.dry_run {
+        print_line(&format!("{} (gitcmd builtin)", line.trim()));
+        return Ok(None);
+    } else if args.len() == 1 && args[0] == "setup" {
+        setup_git_conf_profile();
+        return Ok(None);
+    } else if args.len() == 2 && args[0] == "which" {
+        for line in describe_alias(&args[1], aliases) {
+            print_line(&line);
+        }
+        return Ok(None);
+    }
+
+    // Checked before anything is added to the arguments, so that patterns
+    // see the command as typed
+    if let Some(pattern) = find_dangerous(&args, options.guard).filter(|_| !options.dry_run) {
+        // Nothing can be confirmed without the terminal, so background jobs, `@`
+        // lines and `-c` refuse dangerous commands instead of running them
+        if options.terminal.is_none() {
+            return Err(format!(
+                "`git {}` matches the dangerous pattern `{}` and cannot be confirmed here, so it was not run.",
+                args.join(" "),
+                pattern
+            ));
+        }
+        if !confirm(&args, pattern).map_err(|e| format!("Failed to ask for confirmation: {}", e))? {
+            return Err(String::from("Cancelled."));
+        }
+    }
+
+    // Git only gets forced colors when it is writing straight to the terminal
+    let color =
+        filters.is_empty() && !redirects.iter().any(|r| !r.stderr) && io::stdout().is_terminal();
+    if !redirects.iter().any(|r| r.stderr) {
+        request_progress(&mut args);
+    }
+    let mut stages = vec![PipelineStage {
+        command: git_command(&args, color),
+        redirects,
+        interactive: is_interactive(&args),
+    }];
+    stages.extend(filters);
+    Ok(Some(stages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        tokenize(line).expect("line should tokenize")
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(words("  log  --oneline\t-5 "), ["log", "--oneline", "-5"]);
+        assert!(words("   ").is_empty());
+    }
+
+    #[test]
+    fn single_quotes_are_literal() {
+        assert_eq!(
+            words(r#"commit -m 'a "b" \n $x'"#),
+            ["commit", "-m", r#"a "b" \n $x"#]
+        );
+    }
+
+    #[test]
+    fn double_quotes_keep_spaces_and_handle_escapes() {
+        assert_eq!(words(r#"log --format="%h %s""#), ["log", "--format=%h %s"]);
+        assert_eq!(
+            words(r#""say \"hi\" \$HOME \\ \n""#),
+            [r#"say "hi" $HOME \ \n"#]
+        );
+    }
+
+    #[test]
+    fn adjacent_quotes_form_one_word() {
+        assert_eq!(words(r#""a""b""#), ["ab"]);
+        assert_eq!(words(r#"'a'"b"c"#), ["abc"]);
+        assert_eq!(words(r"'don'\''t'"), ["don't"]);
+    }
+
+    #[test]
+    fn empty_quotes_are_an_argument() {
+        assert_eq!(words(r#"commit -m """#), ["commit", "-m", ""]);
+        assert_eq!(words("x ''"), ["x", ""]);
+    }
+
+    #[test]
+    fn backslash_escapes_outside_quotes() {
+        assert_eq!(words(r"add my\ file.txt \*"), ["add", "my file.txt", "*"]);
+        assert_eq!(words(r"trailing\"), [r"trailing\"]);
+    }
+
+    #[test]
+    fn unterminated_quotes_are_errors() {
+        assert_eq!(
+            tokenize("commit -m 'oops"),
+            Err(String::from("Unterminated single quote."))
+        );
+        assert_eq!(
+            tokenize(r#"commit -m "oops"#),
+            Err(String::from("Unterminated double quote."))
+        );
+        assert_eq!(
+            tokenize(r#"commit -m "oops\""#),
+            Err(String::from("Unterminated double quote."))
+        );
+    }
+
+    #[test]
+    fn quoting_controls_expansion() {
+        env::set_var("GITCMD_TEST_BASE", "main feature");
+        assert_eq!(words("diff $GITCMD_TEST_BASE"), ["diff", "main", "feature"]);
+        assert_eq!(
+            words(r#"diff "$GITCMD_TEST_BASE""#),
+            ["diff", "main feature"]
+        );
+        assert_eq!(
+            words("diff '$GITCMD_TEST_BASE'"),
+            ["diff", "$GITCMD_TEST_BASE"]
+        );
+        assert_eq!(
+            words(r"diff \$GITCMD_TEST_BASE"),
+            ["diff", "$GITCMD_TEST_BASE"]
+        );
+        assert_eq!(words("x${GITCMD_TEST_BASE}y"), ["xmain", "featurey"]);
+        assert_eq!(words("${GITCMD_TEST_UNSET:-origin/main}"), ["origin/main"]);
+        assert!(words("$GITCMD_TEST_UNSET").is_empty());
+        assert_eq!(words("cost $5 $"), ["cost", "$5", "$"]);
+    }
+
+    #[test]
+    fn leading_tilde_is_home() {
+        let home = home_dir().unwrap().to_string_lossy().to_string();
+        assert_eq!(
+            words("add ~/notes ~"),
+            ["add", &format!("{}/notes", home), &home]
+        );
+        assert_eq!(words("add '~/notes' a~b"), ["add", "~/notes", "a~b"]);
+    }
+
+    #[test]
+    fn unquoted_wildcards_expand() {
+        let dir = env::temp_dir().join(format!("gitcmd-glob-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a.rs", "b.rs", "c.rs"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let dir_text = dir.to_string_lossy();
+        let path = |name: &str| format!("{}/{}", dir_text, name);
+
+        assert_eq!(
+            words(&format!("add {}/[ab].rs", dir_text)),
+            ["add", &path("a.rs"), &path("b.rs")]
+        );
+        assert_eq!(
+            words(&format!("add {}/[!ab].rs", dir_text)),
+            ["add", &path("c.rs")]
+        );
+        assert_eq!(words(&format!("add {}/?.rs", dir_text)).len(), 4);
+        // Quoted, the same characters are matched literally
+        assert_eq!(
+            words(&format!("add '{}/[ab].rs'", dir_text)),
+            ["add", &path("[ab].rs")]
+        );
+        assert_eq!(
+            words(&format!(r"add {}/\[ab\].rs", dir_text)),
+            ["add", &path("[ab].rs")]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn quote_arg_round_trips() {
+        for arg in ["plain", "two words", "", "it's", r#"a "b" $c \d"#, "*.rs"] {
+            assert_eq!(words(&quote_arg(arg)), [arg]);
+        }
+        assert_eq!(quote_arg("origin/main"), "origin/main");
+    }
+
+    #[test]
+    fn words_are_split_as_typed() {
+        assert_eq!(
+            split_words(r#"a 'b c'  "d $(x "y")" e\ f"#).unwrap(),
+            ["a", "'b c'", r#""d $(x "y")""#, r"e\ f"]
+        );
+        assert!(split_words("'open").is_err());
+        for word in ["'two words'", r#""a \"b\"""#, r"a\ b", "'$x'", r"it\'s"] {
+            let requoted = format!("\"{}\"", requote_in_double_quotes(word));
+            assert_eq!(words(&requoted), words(word), "{}", requoted);
+        }
+    }
+
+    #[test]
+    fn list_operators_inside_quotes_are_ignored() {
+        let items = parse_command_list(r#"commit -m "a && b; c" && log 'x|y'"#).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].op, ListOp::And);
+        assert!(items[1].filters.is_empty());
+        assert!(parse_command_list("log 'unterminated && x").is_err());
+    }
+
+    fn ops(line: &str) -> Vec<ListOp> {
+        let items = parse_command_list(line).expect("line should parse");
+        items.iter().map(|item| item.op).collect()
+    }
+
+    #[test]
+    fn list_operators_follow_the_last_status() {
+        assert_eq!(
+            ops("fetch || pull; status && log"),
+            [ListOp::Seq, ListOp::Or, ListOp::Seq, ListOp::And]
+        );
+        assert!(ListOp::Seq.runs_after(1));
+        assert!(ListOp::And.runs_after(0) && !ListOp::And.runs_after(1));
+        assert!(ListOp::Or.runs_after(128) && !ListOp::Or.runs_after(0));
+        // A trailing `;` ends the list
+        assert_eq!(ops("status;"), [ListOp::Seq]);
+    }
+
+    #[test]
+    fn groups_nest() {
+        let items = parse_command_list("(fetch && (rebase || merge)); log").unwrap();
+        assert_eq!(items.len(), 2);
+        let group = match &items[0].node {
+            ListNode::Group(group) => group,
+            node => panic!("expected a group, got {:?}", node),
+        };
+        assert_eq!(group[1].op, ListOp::And);
+        assert!(matches!(&group[1].node, ListNode::Group(inner) if inner[1].op == ListOp::Or));
+        assert_eq!(
+            display_list(&items),
+            "(git fetch && (git rebase || git merge)); git log"
+        );
+    }
+
+    #[test]
+    fn malformed_lists_are_syntax_errors() {
+        assert_eq!(
+            parse_command_list("(status &&)").unwrap_err(),
+            "Syntax error near unexpected token `)`."
+        );
+        for line in ["()", "(status", "status)", "&& status", "status ||", "a;;b"] {
+            assert!(
+                parse_command_list(line).is_err(),
+                "{} should not parse",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn bang_commands_go_to_the_shell() {
+        let items = parse_command_list("!cargo test && push | cat").unwrap();
+        assert!(matches!(&items[0].node, ListNode::Local(script) if script == "cargo test"));
+        assert!(matches!(&items[1].node, ListNode::Git(text) if text == "push"));
+        assert_eq!(display_list(&items), "!cargo test && git push | cat");
+        assert!(parse_command_list("status && !").is_err());
+    }
+
+    #[test]
+    fn ampersand_starts_a_background_job() {
+        let items = parse_command_list("status; fetch && rebase & log").unwrap();
+        assert_eq!(items.len(), 3);
+        assert!(!items[0].background);
+        assert!(items[1].background);
+        assert!(matches!(&items[1].node, ListNode::Group(group) if group.len() == 2));
+        assert!(!items[2].background);
+        assert_eq!(
+            display_list(&items),
+            "git status; (git fetch && git rebase) & git log"
+        );
+
+        let items = parse_command_list("log 2>&1 && diff &>out").unwrap();
+        assert!(items.iter().all(|item| !item.background));
+        assert!(parse_command_list("& status").is_err());
+        // They are redirections gitcmd doesn't support, rather than arguments
+        for text in ["log 2>&1", "diff &>out", "diff &>>out"] {
+            assert!(split_redirects(text).is_err(), "{} should be refused", text);
+        }
+    }
+
+    #[test]
+    fn redirects_respect_quotes() {
+        let (text, redirects) = split_redirects(r#"log --grep="a > b" > 'out file'"#).unwrap();
+        assert_eq!(text, r#"log --grep="a > b""#);
+        assert_eq!(redirects.len(), 1);
+        assert_eq!(redirects[0].path, "out file");
+    }
+
+    #[test]
+    fn directory_builtins_are_refused_in_lists() {
+        let options = RunOptions {
+            terminal: None,
+            pager: false,
+            jobs: None,
+            dry_run: true,
+            guard: &[],
+        };
+        for line in [
+            "cd ../other && status",
+            "status; cd -",
+            "pwd | cat",
+            "(popd)",
+        ] {
+            let mut items = parse_command_list(line).unwrap();
+            while let ListNode::Group(group) = &items[0].node {
+                items = group.clone();
             }
+            let refused = items
+                .iter()
+                .any(|item| build_pipeline(item, &[], &options).is_err());
+            assert!(refused, "{} should be refused", line);
         }
     }
-    true
 }
@@ -0,0 +1,281 @@
+// Background jobs started with `&` in the REPL. Each job runs its commands on
+// a thread of its own, and their output is kept until it is asked for with `fg`.
+
+use crate::executor::{print_output, set_current_job, RunOptions};
+use crate::input_handler::InputHandler;
+use std::cell::RefCell;
+use std::env;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// How often `fg` checks for new output and for Ctrl-C
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// What the thread running a job shares with the REPL
+#[derive(Default)]
+pub struct JobState {
+    output: Mutex<Vec<u8>>,
+    // Process groups of the commands running right now
+    groups: Mutex<Vec<u32>>,
+    // Set by Ctrl-C in `fg`, so that no further commands are started
+    interrupted: AtomicBool,
+    // Where the job was started
+    dir: Option<PathBuf>,
+}
+
+impl JobState {
+    // State for a job whose commands run in `dir`
+    pub fn in_dir(dir: Option<PathBuf>) -> Self {
+        JobState {
+            dir,
+            ..JobState::default()
+        }
+    }
+
+    pub fn write(&self, bytes: &[u8]) {
+        lock(&self.output).extend_from_slice(bytes);
+    }
+
+    pub fn set_groups(&self, groups: Vec<u32>) {
+        *lock(&self.groups) = groups;
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    pub fn interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    fn signal(&self, signal: libc::c_int) {
+        for &group in lock(&self.groups).iter() {
+            unsafe {
+                libc::kill(-(group as libc::pid_t), signal);
+            }
+        }
+    }
+
+    fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::Relaxed);
+        self.signal(libc::SIGINT);
+    }
+}
+
+struct Job {
+    id: usize,
+    line: String,
+    state: Arc<JobState>,
+    thread: Option<JoinHandle<i32>>,
+    status: Option<i32>,
+    announced: bool,
+}
+
+impl Job {
+    // The exit code once the job has finished
+    fn status(&mut self) -> Option<i32> {
+        if self.status.is_none() && self.thread.as_ref().is_some_and(|t| t.is_finished()) {
+            let thread = self.thread.take()?;
+            self.status = Some(thread.join().unwrap_or(1));
+        }
+        self.status
+    }
+
+    fn describe(&mut self) -> String {
+        let state = match self.status() {
+            None => String::from("Running"),
+            Some(0) => String::from("Done"),
+            Some(status) => format!("Exit {}", status),
+        };
+        format!("[{}] {:<8} {}", self.id, state, self.line)
+    }
+}
+
+#[derive(Default)]
+pub struct Jobs {
+    jobs: RefCell<Vec<Job>>,
+}
+
+impl Jobs {
+    // Runs `run` on a new thread and returns the number of the job
+    pub fn start<F>(&self, line: String, run: F) -> usize
+    where
+        F: FnOnce(&RunOptions) -> i32 + Send + 'static,
+    {
+        self.start_in(line, env::current_dir().ok(), run)
+    }
+
+    // Like `start`, with the commands of the job running in `dir`
+    pub fn start_in<F>(&self, line: String, dir: Option<PathBuf>, run: F) -> usize
+    where
+        F: FnOnce(&RunOptions) -> i32 + Send + 'static,
+    {
+        let state = Arc::new(JobState::in_dir(dir));
+        let shared = Arc::clone(&state);
+        let thread = thread::spawn(move || {
+            set_current_job(Some(shared));
+            let options = RunOptions {
+                terminal: None,
+                pager: false,
+                jobs: None,
+                dry_run: false,
+                guard: &[],
+            };
+            run(&options)
+        });
+
+        let mut jobs = self.jobs.borrow_mut();
+        // Numbers are reused once the jobs using them are gone, like in a shell
+        let id = jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        jobs.push(Job {
+            id,
+            line,
+            state,
+            thread: Some(thread),
+            status: None,
+            announced: false,
+        });
+        id
+    }
+
+    pub fn running(&self) -> usize {
+        let mut jobs = self.jobs.borrow_mut();
+        jobs.iter_mut()
+            .map(|job| job.status())
+            .filter(Option::is_none)
+            .count()
+    }
+
+    pub fn interrupt_all(&self) {
+        for job in self.jobs.borrow_mut().iter_mut() {
+            if job.status().is_none() {
+                job.state.interrupt();
+            }
+        }
+    }
+
+    pub fn stop_all(&self) {
+        for job in self.jobs.borrow_mut().iter_mut() {
+            if job.status().is_none() {
+                job.state.signal(libc::SIGTERM);
+            }
+        }
+    }
+
+    // Lines for the jobs that finished since the last call. Jobs that printed
+    // nothing are forgotten then, the others wait for `fg`.
+    pub fn announce(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut jobs = self.jobs.borrow_mut();
+        for job in jobs.iter_mut() {
+            if !job.announced && job.status().is_some() {
+                job.announced = true;
+                lines.push(job.describe());
+            }
+        }
+        jobs.retain(|job| !job.announced || !lock(&job.state.output).is_empty());
+        lines
+    }
+
+    // Shows the output of a job, following it until it finishes, and returns
+    // its exit code. The job is forgotten afterwards.
+    pub fn foreground(&self, id: usize) -> Option<i32> {
+        let mut job = {
+            let mut jobs = self.jobs.borrow_mut();
+            let index = jobs.iter().position(|job| job.id == id)?;
+            jobs.remove(index)
+        };
+
+        print_output(format!("\r\n\x1b[K{}\n", job.line).as_bytes());
+        let mut shown = 0;
+        loop {
+            // Checked before reading, so that no output written last is missed
+            let status = job.status();
+            let output = lock(&job.state.output)[shown..].to_vec();
+            shown += output.len();
+            print_output(&output);
+            if let Some(status) = status {
+                return Some(status);
+            }
+            if ctrl_c_pressed() {
+                job.state.interrupt();
+            }
+        }
+    }
+}
+
+// Waits a moment for a key and tells whether it was Ctrl-C. The terminal is
+// in raw mode, so Ctrl-C arrives as a byte rather than a signal. Anything else
+// typed while a job is in the foreground is dropped.
+fn ctrl_c_pressed() -> bool {
+    let mut poll = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ready = unsafe { libc::poll(&mut poll, 1, POLL_INTERVAL.as_millis() as libc::c_int) };
+    if ready <= 0 {
+        return false;
+    }
+    let mut buffer = [0; 64];
+    match io::stdin().read(&mut buffer) {
+        Ok(0) | Err(_) => {
+            // Stdin was closed; don't spin on it
+            thread::sleep(POLL_INTERVAL);
+            false
+        }
+        Ok(read) => buffer[..read].contains(&3),
+    }
+}
+
+// Runs the `jobs` and `fg` builtins and returns their exit code, or `None`
+// when the line is neither
+pub fn handle_job_builtin<W: Write>(
+    line: &str,
+    jobs: &Jobs,
+    input_handler: &mut InputHandler<W>,
+) -> io::Result<Option<i32>> {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("jobs"), None, _) => {
+            input_handler.write_line("")?;
+            let mut list = jobs.jobs.borrow_mut();
+            if list.is_empty() {
+                input_handler.write_line("No jobs.")?;
+            }
+            for job in list.iter_mut() {
+                input_handler.write_line(&job.describe())?;
+                // Listed as finished here, so the prompt needn't say it again
+                job.announced = job.status().is_some();
+            }
+            Ok(Some(0))
+        }
+        (Some("fg"), target, None) => {
+            // Without a number, the most recent job comes forward
+            let id = match target {
+                Some(target) => target.trim_start_matches('%').parse().ok(),
+                None => jobs.jobs.borrow().iter().map(|job| job.id).max(),
+            };
+            match id.and_then(|id| jobs.foreground(id)) {
+                Some(status) => Ok(Some(status)),
+                None => {
+                    input_handler.write_line("")?;
+                    input_handler.write_line(&match target {
+                        Some(target) => format!("fg: no such job: {}", target),
+                        None => String::from("fg: no current job"),
+                    })?;
+                    Ok(Some(1))
+                }
+            }
+        }
+        _ => Ok(None),
+    }
+}
@@ -1,17 +1,30 @@
 use std::env;
 use std::process;
 
+mod alias;
 mod config;
 mod config_io;
+mod directory;
+mod executor;
+mod glob;
+mod guard;
 mod input_handler;
 mod input_parser;
+mod jobs;
+mod suggest;
 mod terminal;
+mod undo;
+mod workspace;
 
 fn print_usage() {
     println!("GitCmd - Git Configuration Manager");
     println!("\nUsage:");
     println!("  gitcmd");
     println!("      Starts the gitcmd terminal");
+    println!("  gitcmd -c <line>");
+    println!("      Runs one line like the terminal would and exits with its status");
+    println!("  gitcmd --dry-run -c <line>");
+    println!("      Prints the commands the line would run without running them");
     println!("  gitcmd <command>");
     println!("      setup     Run the configuration wizard");
     println!("      help      Show this help message");
@@ -30,6 +43,18 @@ fn main() {
             "help" => {
                 print_usage();
             }
+            "-c" | "--dry-run" => {
+                let dry_run = args[1] == "--dry-run";
+                let rest = if dry_run { &args[2..] } else { &args[1..] };
+                match (rest.first().map(String::as_str), rest.get(1)) {
+                    (Some("-c"), Some(line)) => process::exit(terminal::run_line(line, dry_run)),
+                    _ => {
+                        println!("Missing line after -c");
+                        print_usage();
+                        process::exit(1);
+                    }
+                }
+            }
             _ => {
                 println!("Unknown command: {}", args[1]);
                 print_usage();
@@ -0,0 +1,169 @@
+// "Did you mean" suggestions for a mistyped command at the start of a REPL
+// line. The word is compared with git's commands, the aliases in gitcmd.conf
+// and the REPL's own builtins.
+
+use crate::config_io::GitCmdAlias;
+use crate::input_handler::InputHandler;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+// Words the REPL handles itself
+pub const BUILTINS: [&str; 18] = [
+    "exit", "quit", "clear", "cls", "jobs", "fg", "undo", "alias", "unalias", "which", "setup",
+    "cd", "pwd", "pushd", "popd", ":pager", ":dry", ":nopager",
+];
+
+pub enum Correction {
+    // The word is known, or nothing is close enough to suggest
+    NotNeeded,
+    Declined,
+    // The line with the word replaced by the suggestion that was picked
+    Picked(String),
+}
+
+// How many suggestions are offered at most
+const MAX_SUGGESTIONS: usize = 3;
+
+// Git's commands and git aliases, as listed by `git help -a`. They are read
+// once, the first time a word is not recognised.
+fn git_commands() -> &'static [String] {
+    static COMMANDS: OnceLock<Vec<String>> = OnceLock::new();
+    COMMANDS.get_or_init(|| {
+        let output = match Command::new("git")
+            .args(["help", "-a"])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+        {
+            Ok(output) => output,
+            Err(_) => return Vec::new(),
+        };
+        let mut commands = Vec::new();
+        // The lists of guides at the end name documents, not commands
+        let mut guides = false;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            if !line.starts_with(' ') {
+                guides = line.ends_with("interfaces");
+            } else if !guides {
+                commands.extend(line.split_whitespace().next().map(str::to_string));
+            }
+        }
+        commands
+    })
+}
+
+// Optimal string alignment distance: insertions, deletions, substitutions and
+// swaps of two neighbouring characters each count as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+// The closest of `candidates` to `word`, nearest first. Short words allow
+// fewer edits, so that `st` does not suggest half of git.
+fn closest<'a>(word: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let limit = if word.chars().count() <= 3 { 1 } else { 2 };
+    let mut matches: Vec<(usize, &str)> = candidates
+        .map(|candidate| (edit_distance(word, candidate), candidate))
+        .filter(|&(distance, _)| distance <= limit)
+        .collect();
+    matches.sort();
+    matches.dedup_by(|a, b| a.1 == b.1);
+    matches
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+// The command word a line starts with, if it is one that could be mistyped
+fn command_word(line: &str) -> Option<&str> {
+    let word = line.split(char::is_whitespace).next()?;
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    Some(word).filter(|_| plain && word != "git")
+}
+
+// Checks the first word of `line` and, when it is not a known command but
+// close to one, offers the closest matches to pick from with a single key
+pub fn suggest_correction<W: Write>(
+    line: &str,
+    aliases: &[GitCmdAlias],
+    input_handler: &mut InputHandler<W>,
+) -> io::Result<Correction> {
+    let word = match command_word(line) {
+        Some(word) => word,
+        None => return Ok(Correction::NotNeeded),
+    };
+    let known = BUILTINS
+        .iter()
+        .copied()
+        .chain(aliases.iter().map(|alias| alias.identifier.as_str()))
+        .chain(git_commands().iter().map(String::as_str));
+    if known.clone().any(|command| command == word) {
+        return Ok(Correction::NotNeeded);
+    }
+    // Without anything close, git reports the unknown command itself
+    let suggestions = closest(word, known);
+    if suggestions.is_empty() {
+        return Ok(Correction::NotNeeded);
+    }
+
+    input_handler.write_line("")?;
+    input_handler.write_line(&format!("Unknown command `{}`. Did you mean:", word))?;
+    for (number, suggestion) in suggestions.iter().enumerate() {
+        input_handler.write_line(&format!("  {}) {}", number + 1, suggestion))?;
+    }
+    input_handler.write_str("Press a number to run it, or any other key to cancel.")?;
+    let key = input_handler.read_key()?;
+    input_handler.write_line("")?;
+
+    let choice = key
+        .and_then(|key| key.to_digit(10))
+        .and_then(|number| (number as usize).checked_sub(1));
+    Ok(match choice.and_then(|index| suggestions.get(index)) {
+        Some(suggestion) => Correction::Picked(format!("{}{}", suggestion, &line[word.len()..])),
+        None => Correction::Declined,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typos_are_close_to_their_command() {
+        assert_eq!(edit_distance("stauts", "status"), 1);
+        assert_eq!(edit_distance("comit", "commit"), 1);
+        assert_eq!(edit_distance("psuh", "push"), 1);
+        assert_eq!(edit_distance("log", "blame"), 4);
+
+        let candidates = ["status", "stash", "stage", "push", "pull"];
+        assert_eq!(closest("stauts", candidates.into_iter()), ["status"]);
+        assert_eq!(closest("pul", candidates.into_iter()), ["pull"]);
+        assert!(closest("xyz", candidates.into_iter()).is_empty());
+    }
+}
@@ -1,19 +1,113 @@
-use std::io;
+use std::io::{self, Write};
+use crate::alias::handle_alias_builtin;
+use crate::config_io::read_gitcmd_conf;
+use crate::directory::{current_dir_display, handle_directory_builtin, Directories};
+use crate::executor::{last_status, set_last_status, RunOptions};
+use crate::guard::dangerous_patterns;
 use crate::input_handler::InputHandler;
 use crate::input_parser::parse_and_execute_line;
+use crate::jobs::{handle_job_builtin, Jobs};
+use crate::suggest::{suggest_correction, Correction};
+use crate::undo::handle_undo_builtin;
+use crate::workspace::handle_workspace_line;
+
+// Settings of the running REPL, changed with `:<name> <value>`
+struct Settings {
+    pager: bool,
+    // Print commands instead of running them
+    dry: bool,
+}
+
+fn parse_switch(value: &str) -> Option<bool> {
+    match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+// Shows or changes a setting. Returns false when the line is not one.
+fn handle_setting<W: Write>(
+    input: &str,
+    settings: &mut Settings,
+    input_handler: &mut InputHandler<W>,
+) -> io::Result<bool> {
+    let mut words = input.split_whitespace();
+    let (name, value) = match words.next() {
+        Some(":pager") => ("pager", &mut settings.pager),
+        Some(":dry") => ("dry", &mut settings.dry),
+        _ => return Ok(false),
+    };
+
+    input_handler.write_line("")?;
+    match (words.next().map(parse_switch), words.next()) {
+        (None, _) => {}
+        (Some(Some(switch)), None) => *value = switch,
+        _ => {
+            input_handler.write_line(&format!("Usage: :{} [on|off]", name))?;
+            return Ok(true);
+        }
+    }
+    let state = if *value { "on" } else { "off" };
+    input_handler.write_line(&format!("{}: {}", name, state))?;
+    Ok(true)
+}
+
+// Runs one line without the REPL, e.g. from a script, and returns its exit code
+pub fn run_line(line: &str, dry_run: bool) -> i32 {
+    let config = read_gitcmd_conf().unwrap_or_default();
+    let guard = dangerous_patterns(&config);
+    let options = RunOptions {
+        terminal: None,
+        pager: false,
+        jobs: None,
+        dry_run,
+        guard: &guard,
+    };
+    parse_and_execute_line(line.trim().to_string(), &config.git_cmds, &options)
+}
 
 pub fn terminal_loop() -> io::Result<()> {
     let mut prev_commands: Vec<String> = Vec::new();
     let mut history_index: Option<usize> = None;
     let mut input_handler = InputHandler::new_raw()?;
+    // A missing or unreadable config just means there are no aliases yet
+    let mut config = read_gitcmd_conf().unwrap_or_default();
+    let mut settings = Settings {
+        pager: true,
+        dry: false,
+    };
+    let jobs = Jobs::default();
+    let mut warned_about_jobs = false;
+    let mut directories = Directories::default();
+    // A corrected line picked from the suggestions, to run next
+    let mut pending: Option<String> = None;
 
     loop {
         println!();
-        let input = match input_handler.read_line_with_history(
-            "\rgitcmd > ",
-            &prev_commands,
-            &mut history_index,
-        )? {
+        for line in jobs.announce() {
+            input_handler.write_line(&line)?;
+        }
+        // The prompt shows where commands run, and the exit code of the last
+        // command when it failed
+        let dir = current_dir_display();
+        let prompt = match last_status() {
+            0 => format!("\rgitcmd {} > ", dir),
+            status => format!("\rgitcmd {} [{}] > ", dir, status),
+        };
+        let typed = match pending.take() {
+            // Shown as if it had been typed
+            Some(line) => {
+                input_handler.write_line(&format!("{}{}", prompt, line))?;
+                Some(line)
+            }
+            None => input_handler.read_line_with_history(
+                &prompt,
+                &prev_commands,
+                &mut history_index,
+            )?,
+        };
+        let input = match typed {
             Some(input) => {
                 prev_commands.push(input.clone());
                 input
@@ -26,14 +120,76 @@ pub fn terminal_loop() -> io::Result<()> {
 
         let input = input.trim();
         if input == "exit" || input == "quit" {
+            // Like a shell, warn once before leaving jobs behind
+            if jobs.running() > 0 && !warned_about_jobs {
+                warned_about_jobs = true;
+                input_handler.write_line("")?;
+                input_handler.write_line("There are running jobs. Exit again to stop them.")?;
+                continue;
+            }
+            jobs.stop_all();
             input_handler.write_line("\r\nExiting...\r\n")?;
             break;
         }
+        warned_about_jobs = false;
 
-        if input == "clear" || input == "cls" {
+        let cleared = input == "clear" || input == "cls";
+        if cleared {
             input_handler.clear_screen().expect("FATAL: Failed to clear screen!");
         }
-        if !input.is_empty() && parse_and_execute_line(input.to_string()) {
+        // Alias bodies are taken verbatim, so these builtins see the raw line
+        let mut builtin_status = handle_job_builtin(input, &jobs, &mut input_handler)?;
+        if builtin_status.is_none() {
+            builtin_status = handle_undo_builtin(input, settings.dry, &mut input_handler)?;
+        }
+        if builtin_status.is_none() {
+            builtin_status = handle_directory_builtin(input, &mut directories, &mut input_handler)?;
+        }
+        if builtin_status.is_none() {
+            builtin_status =
+                handle_workspace_line(input, &config, settings.dry, &mut input_handler)?;
+        }
+        let handled = cleared
+            || builtin_status.is_some()
+            || handle_alias_builtin(input, &mut config, settings.dry, &mut input_handler)?
+            || handle_setting(input, &mut settings, &mut input_handler)?;
+        if handled {
+            set_last_status(builtin_status.unwrap_or(0));
+        }
+
+        // `:nopager <command>` runs a single line without the pager
+        let (line, pager) = match input.strip_prefix(":nopager") {
+            Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => {
+                (rest.trim(), false)
+            }
+            _ => (input, settings.pager),
+        };
+        if !handled && !line.is_empty() {
+            match suggest_correction(line, &config.git_cmds, &mut input_handler)? {
+                Correction::NotNeeded => {}
+                Correction::Declined => {
+                    set_last_status(1);
+                    continue;
+                }
+                // Keeps a `:nopager` prefix
+                Correction::Picked(corrected) => {
+                    pending = Some(format!("{}{}", &input[..input.len() - line.len()], corrected));
+                    continue;
+                }
+            }
+        }
+        let guard = dangerous_patterns(&config);
+        let options = RunOptions {
+            terminal: Some(&input_handler.stdout),
+            pager,
+            jobs: Some(&jobs),
+            dry_run: settings.dry,
+            guard: &guard,
+        };
+        if handled
+            || (!line.is_empty()
+                && parse_and_execute_line(line.to_string(), &config.git_cmds, &options) == 0)
+        {
             prev_commands.push(input.to_string());
         }
         history_index = None;
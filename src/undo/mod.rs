@@ -0,0 +1,421 @@
+// The `undo` builtin. It reads the reflog to tell what the last operation on
+// the current branch was, and resets the branch to where it was before.
+
+use crate::executor::{
+    capture_git, capture_git_lines, describe_pipeline, git_command, PipelineStage,
+};
+use crate::input_handler::InputHandler;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Stdio;
+
+// How many earlier states `undo --list` offers
+const LIST_LENGTH: usize = 15;
+
+// One reflog entry: where the ref pointed after an operation
+struct Entry {
+    hash: String,
+    when: String,
+    subject: String,
+}
+
+impl Entry {
+    fn short_hash(&self) -> &str {
+        &self.hash[..self.hash.len().min(7)]
+    }
+}
+
+// The newest `count` entries of the reflog of `reference`
+fn read_reflog(reference: &str, count: usize) -> Result<Vec<Entry>, String> {
+    let output = capture_git(&[
+        "reflog",
+        "show",
+        &format!("-n{}", count),
+        "--date=relative",
+        "--format=%H%x09%gd%x09%gs",
+        reference,
+    ])?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let hash = fields.next()?.to_string();
+            // The selector is `main@{5 minutes ago}`
+            let selector = fields.next()?;
+            let when = selector
+                .split_once("@{")
+                .map_or(selector, |(_, when)| when.trim_end_matches('}'))
+                .to_string();
+            let subject = fields.next().unwrap_or_default().to_string();
+            Some(Entry {
+                hash,
+                when,
+                subject,
+            })
+        })
+        .collect())
+}
+
+// The current branch as a full ref and a display name, or HEAD when detached
+fn current_ref() -> (String, String) {
+    match capture_git(&["symbolic-ref", "-q", "HEAD"]) {
+        Ok(reference) => {
+            let name = reference.trim_start_matches("refs/heads/").to_string();
+            (reference, name)
+        }
+        Err(_) => (String::from("HEAD"), String::from("HEAD")),
+    }
+}
+
+// Turns a reflog subject like `commit (amend): Fix typo` into plain words
+fn explain(subject: &str) -> String {
+    let (action, detail) = subject.split_once(": ").unwrap_or((subject, ""));
+    match action {
+        "commit" => format!("committed \"{}\"", detail),
+        "commit (amend)" => format!("amended the last commit, now \"{}\"", detail),
+        "commit (initial)" => format!("made the first commit \"{}\"", detail),
+        "commit (merge)" => format!("committed a merge \"{}\"", detail),
+        "reset" => format!("reset the branch, {}", detail),
+        "cherry-pick" => format!("cherry-picked \"{}\"", detail),
+        "revert" => format!("reverted \"{}\"", detail),
+        "branch" => format!("created the branch ({})", detail),
+        _ if action.starts_with("rebase") => format!("rebased ({})", detail),
+        _ if action.starts_with("pull") => format!("pulled ({})", detail),
+        _ if action.starts_with("merge ") => format!("merged {} ({})", &action[6..], detail),
+        _ => subject.to_string(),
+    }
+}
+
+// The branches of a `checkout: moving from main to topic` entry
+fn switched(subject: &str) -> Option<(&str, &str)> {
+    subject
+        .strip_prefix("checkout: moving from ")?
+        .split_once(" to ")
+}
+
+// Why undo can't run right now, if it can't
+fn blocker() -> Option<Vec<String>> {
+    if capture_git(&["rev-parse", "--git-dir"]).is_err() {
+        return Some(vec![String::from(
+            "undo only works inside a git repository.",
+        )]);
+    }
+    let in_progress = |path: &str| {
+        capture_git(&["rev-parse", "--git-path", path]).is_ok_and(|path| Path::new(&path).exists())
+    };
+    if in_progress("rebase-merge") || in_progress("rebase-apply") {
+        return Some(vec![String::from(
+            "A rebase is in progress. Finish it with `rebase --continue` or give it up with `rebase --abort`.",
+        )]);
+    }
+    if in_progress("MERGE_HEAD") {
+        return Some(vec![String::from(
+            "A merge is in progress. Finish it with `commit` or give it up with `merge --abort`.",
+        )]);
+    }
+    None
+}
+
+// Tracked files whose staged or working tree version is in neither `HEAD` nor
+// `target`, and so would be lost by `reset --hard target`. Changes left behind
+// by `reset --soft` or a plain `reset` match `target` and are kept.
+fn lost_changes(target: &str) -> Vec<String> {
+    let changed = |args: &[&str]| capture_git_lines(&[&["diff", "--name-only"], args].concat());
+    let mut lost = Vec::new();
+    for cached in [&["--cached"][..], &[]] {
+        let since_target = changed(&[cached, &[target]].concat());
+        for path in changed(&[cached, &["HEAD"]].concat()) {
+            if since_target.contains(&path) && !lost.contains(&path) {
+                lost.push(path);
+            }
+        }
+    }
+    lost
+}
+
+fn fail<W: Write>(input_handler: &mut InputHandler<W>, lines: &[String]) -> io::Result<i32> {
+    for line in lines {
+        input_handler.write_line(line)?;
+    }
+    Ok(1)
+}
+
+fn ask<W: Write>(input_handler: &mut InputHandler<W>, question: &str) -> io::Result<bool> {
+    let answer = input_handler.read_line(question)?.unwrap_or_default();
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+// Runs git with its output shown and returns its exit code. A dry run only
+// shows the command.
+fn run_git<W: Write>(
+    input_handler: &mut InputHandler<W>,
+    args: &[&str],
+    dry_run: bool,
+) -> io::Result<i32> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let mut command = git_command(&args, false);
+    if dry_run {
+        let stage = PipelineStage {
+            command,
+            redirects: Vec::new(),
+            interactive: false,
+        };
+        input_handler.write_line(&describe_pipeline(&[stage]))?;
+        return Ok(0);
+    }
+    let output = command.stdin(Stdio::null()).output()?;
+    for stream in [&output.stdout, &output.stderr] {
+        for line in String::from_utf8_lossy(stream).lines() {
+            input_handler.write_line(line)?;
+        }
+    }
+    Ok(output.status.code().unwrap_or(1))
+}
+
+// Shows which commits moving `name` from `current` to `target` takes away and
+// brings back, then does it once confirmed
+fn go_back<W: Write>(
+    input_handler: &mut InputHandler<W>,
+    name: &str,
+    current: &str,
+    target: &Entry,
+    dry_run: bool,
+) -> io::Result<i32> {
+    let lost = lost_changes(&target.hash);
+    if !lost.is_empty() {
+        let mut lines = vec![String::from(
+            "You have uncommitted changes that undo would throw away. Commit or stash them first:",
+        )];
+        lines.extend(lost.iter().map(|path| format!("    {}", path)));
+        return fail(input_handler, &lines);
+    }
+
+    let changes = [
+        (
+            format!("Commits that will no longer be on {}:", name),
+            format!("{}..{}", target.hash, current),
+        ),
+        (
+            format!("Commits that will be back on {}:", name),
+            format!("{}..{}", current, target.hash),
+        ),
+    ];
+    for (heading, range) in changes {
+        let commits = capture_git(&["log", "--oneline", &range]).unwrap_or_default();
+        if !commits.is_empty() {
+            input_handler.write_line(&heading)?;
+            for commit in commits.lines() {
+                input_handler.write_line(&format!("    {}", commit))?;
+            }
+        }
+    }
+
+    let question = format!(
+        "Move {} back to {} ({})? (y/N) ",
+        name,
+        target.short_hash(),
+        target.when
+    );
+    if !dry_run && !ask(input_handler, &question)? {
+        input_handler.write_line("Cancelled.")?;
+        return Ok(1);
+    }
+    run_git(input_handler, &["reset", "--hard", &target.hash], dry_run)
+}
+
+fn undo_last<W: Write>(input_handler: &mut InputHandler<W>, dry_run: bool) -> io::Result<i32> {
+    // Switching branches only moves HEAD, so it is undone by switching back
+    if let Ok(head) = read_reflog("HEAD", 1) {
+        if let Some((entry, (from, to))) = head
+            .first()
+            .and_then(|entry| Some((entry, switched(&entry.subject)?)))
+        {
+            input_handler.write_line(&format!(
+                "The last operation switched from {} to {} ({}).",
+                from, to, entry.when
+            ))?;
+            if !dry_run && !ask(input_handler, &format!("Switch back to {}? (y/N) ", from))? {
+                input_handler.write_line("Cancelled.")?;
+                return Ok(1);
+            }
+            return run_git(input_handler, &["checkout", from], dry_run);
+        }
+    }
+
+    let (reference, name) = current_ref();
+    let entries = match read_reflog(&reference, 2) {
+        Ok(entries) => entries,
+        Err(e) => return fail(input_handler, &[e]),
+    };
+    if entries.len() < 2 {
+        return fail(
+            input_handler,
+            &[format!(
+                "Nothing to undo: the reflog has no earlier state of {}.",
+                name
+            )],
+        );
+    }
+    input_handler.write_line(&format!(
+        "The last operation on {} {} ({}).",
+        name,
+        explain(&entries[0].subject),
+        entries[0].when
+    ))?;
+    go_back(input_handler, &name, &entries[0].hash, &entries[1], dry_run)
+}
+
+fn undo_from_list<W: Write>(input_handler: &mut InputHandler<W>, dry_run: bool) -> io::Result<i32> {
+    let (reference, name) = current_ref();
+    let entries = match read_reflog(&reference, LIST_LENGTH + 1) {
+        Ok(entries) => entries,
+        Err(e) => return fail(input_handler, &[e]),
+    };
+    if entries.len() < 2 {
+        return fail(
+            input_handler,
+            &[format!(
+                "Nothing to undo: the reflog has no earlier state of {}.",
+                name
+            )],
+        );
+    }
+
+    input_handler.write_line(&format!(
+        "Earlier states of {}, newest first, with the operation that led to each:",
+        name
+    ))?;
+    for (number, entry) in entries.iter().enumerate().skip(1) {
+        input_handler.write_line(&format!(
+            "{:>4}  {}  {:<16}  {}",
+            number,
+            entry.short_hash(),
+            entry.when,
+            explain(&entry.subject)
+        ))?;
+    }
+    let answer = input_handler
+        .read_line("Go back to which one (Enter to cancel)? ")?
+        .unwrap_or_default();
+    match answer.trim().parse::<usize>() {
+        Ok(number) if (1..entries.len()).contains(&number) => go_back(
+            input_handler,
+            &name,
+            &entries[0].hash,
+            &entries[number],
+            dry_run,
+        ),
+        _ => {
+            input_handler.write_line("Cancelled.")?;
+            Ok(1)
+        }
+    }
+}
+
+// Runs `undo` and `undo --list` and returns their exit code, or `None` when
+// the line is neither. A dry run shows the reset or checkout without running it.
+pub fn handle_undo_builtin<W: Write>(
+    line: &str,
+    dry_run: bool,
+    input_handler: &mut InputHandler<W>,
+) -> io::Result<Option<i32>> {
+    let mut words = line.split_whitespace();
+    let list = match (words.next(), words.next(), words.next()) {
+        (Some("undo"), None, _) => false,
+        (Some("undo"), Some("--list"), None) => true,
+        (Some("undo"), _, _) => {
+            input_handler.write_line("")?;
+            input_handler.write_line("Usage: undo [--list]")?;
+            return Ok(Some(1));
+        }
+        _ => return Ok(None),
+    };
+
+    input_handler.write_line("")?;
+    if let Some(reason) = blocker() {
+        return fail(input_handler, &reason).map(Some);
+    }
+    let status = if list {
+        undo_from_list(input_handler, dry_run)?
+    } else {
+        undo_last(input_handler, dry_run)?
+    };
+    Ok(Some(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::set_current_job;
+    use crate::jobs::JobState;
+    use std::env;
+    use std::fs;
+    use std::process::Command;
+    use std::sync::Arc;
+
+    fn run(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args([
+                "-c",
+                "user.name=gitcmd",
+                "-c",
+                "user.email=gitcmd@example.com",
+            ])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[test]
+    fn only_changes_in_neither_commit_block_a_reset() {
+        let dir = env::temp_dir().join(format!("gitcmd-undo-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        // Git runs in the temp repository, like in a job started there
+        set_current_job(Some(Arc::new(JobState::in_dir(Some(dir.clone())))));
+
+        run(&dir, &["init", "-q"]);
+        fs::write(dir.join("notes.txt"), "one\n").unwrap();
+        run(&dir, &["add", "notes.txt"]);
+        run(&dir, &["commit", "-q", "-m", "one"]);
+        fs::write(dir.join("notes.txt"), "two\n").unwrap();
+        run(&dir, &["commit", "-q", "-a", "-m", "two"]);
+        let two = run(&dir, &["rev-parse", "HEAD"]);
+
+        // What `reset --soft` and a plain `reset` leave behind is still in `two`
+        run(&dir, &["reset", "-q", "--soft", "HEAD~1"]);
+        assert!(lost_changes(&two).is_empty());
+        run(&dir, &["reset", "-q", "--hard", &two]);
+        run(&dir, &["reset", "-q", "HEAD~1"]);
+        assert!(lost_changes(&two).is_empty());
+
+        fs::write(dir.join("notes.txt"), "three\n").unwrap();
+        assert_eq!(lost_changes(&two), vec![String::from("notes.txt")]);
+
+        set_current_job(None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reflog_subjects_are_explained() {
+        assert_eq!(
+            explain("commit (amend): Fix typo"),
+            "amended the last commit, now \"Fix typo\""
+        );
+        assert_eq!(
+            explain("reset: moving to HEAD~2"),
+            "reset the branch, moving to HEAD~2"
+        );
+        assert_eq!(
+            explain("rebase (finish): refs/heads/main onto 1a2b3c4"),
+            "rebased (refs/heads/main onto 1a2b3c4)"
+        );
+        assert_eq!(
+            switched("checkout: moving from main to topic"),
+            Some(("main", "topic"))
+        );
+    }
+}
@@ -0,0 +1,222 @@
+// Running a line in several repositories at once, e.g. `@all status -sb` or
+// `@backend pull`. The repositories are listed in the `workspace { }` section
+// of gitcmd.conf as paths or globs, and an entry can be named with `name=path`.
+
+use crate::config_io::GitCmdConfig;
+use crate::directory::display_path;
+use crate::executor::{RunOptions, INTERRUPTED};
+use crate::glob::expand_glob;
+use crate::guard::dangerous_patterns;
+use crate::input_handler::InputHandler;
+use crate::input_parser::parse_and_execute_line;
+use crate::jobs::Jobs;
+use dirs::home_dir;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+struct Repo {
+    name: String,
+    // The name of the entry that listed it, if it was given one
+    group: Option<String>,
+    path: PathBuf,
+}
+
+// The repositories the entries list, in order. Relative paths start at the
+// home directory, and anything that is not a git repository is skipped.
+fn workspace_repos(entries: &[String]) -> Vec<Repo> {
+    let home = home_dir().unwrap_or_default();
+    let mut repos: Vec<Repo> = Vec::new();
+    for entry in entries {
+        let (group, pattern) = match entry.split_once('=') {
+            Some((name, pattern)) => (Some(name.trim().to_string()), pattern.trim()),
+            None => (None, entry.trim()),
+        };
+        let pattern = pattern.strip_prefix("~/").unwrap_or(pattern);
+        let found: Vec<PathBuf> = expand_glob(pattern, &home)
+            .into_iter()
+            .map(|path| home.join(path))
+            .filter(|path| path.join(".git").exists())
+            .collect();
+
+        // A name given to a single repository is its own, otherwise it names
+        // the group and each repository goes by its directory
+        let single = found.len() == 1;
+        for path in found {
+            if repos.iter().any(|repo| repo.path == path) {
+                continue;
+            }
+            let name = match &group {
+                Some(group) if single => group.clone(),
+                _ => path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| path.display().to_string()),
+            };
+            repos.push(Repo {
+                name,
+                group: group.clone(),
+                path,
+            });
+        }
+    }
+    repos
+}
+
+// The repositories `selector` picks: `all`, or names separated by commas
+fn select<'a>(repos: &'a [Repo], selector: &str) -> Result<Vec<&'a Repo>, String> {
+    if selector == "all" {
+        return Ok(repos.iter().collect());
+    }
+    let mut selected: Vec<&Repo> = Vec::new();
+    for name in selector.split(',') {
+        let matching: Vec<&Repo> = repos
+            .iter()
+            .filter(|repo| repo.name == name || repo.group.as_deref() == Some(name))
+            .collect();
+        if matching.is_empty() {
+            return Err(format!("No repository named `{}` in the workspace.", name));
+        }
+        for repo in matching {
+            if !selected.iter().any(|chosen| chosen.path == repo.path) {
+                selected.push(repo);
+            }
+        }
+    }
+    Ok(selected)
+}
+
+// Runs `line` in every repository at once, each as a job of its own. The
+// output is shown one repository at a time, in order, under a header. For a
+// dry run, each repository shows the commands it would run instead.
+fn run_in_repos<W: Write>(
+    repos: &[&Repo],
+    line: &str,
+    config: &GitCmdConfig,
+    dry_run: bool,
+    input_handler: &mut InputHandler<W>,
+) -> io::Result<i32> {
+    let jobs = Jobs::default();
+    let guard = dangerous_patterns(config);
+    let ids: Vec<usize> = repos
+        .iter()
+        .map(|repo| {
+            let header = format!("==> {} ({}) <==", repo.name, display_path(&repo.path));
+            let line = line.to_string();
+            let aliases = config.git_cmds.clone();
+            let guard = guard.clone();
+            jobs.start_in(header, Some(repo.path.clone()), move |options| {
+                let options = RunOptions {
+                    guard: &guard,
+                    dry_run,
+                    ..*options
+                };
+                parse_and_execute_line(line, &aliases, &options)
+            })
+        })
+        .collect();
+
+    let mut failed = Vec::new();
+    for (repo, id) in repos.iter().zip(ids) {
+        let status = jobs.foreground(id).unwrap_or(1);
+        // Ctrl-C stops every repository, not just the one being shown
+        if status == INTERRUPTED {
+            jobs.interrupt_all();
+        }
+        if status != 0 {
+            failed.push((repo, status));
+        }
+    }
+
+    // Nothing ran, so there is nothing to sum up beyond the commands shown
+    if dry_run {
+        return Ok(i32::from(!failed.is_empty()));
+    }
+    input_handler.write_line("")?;
+    if failed.is_empty() {
+        input_handler.write_line(&format!("Succeeded in all {} repositories.", repos.len()))?;
+        return Ok(0);
+    }
+    input_handler.write_line(&format!(
+        "Failed in {} of {} repositories:",
+        failed.len(),
+        repos.len()
+    ))?;
+    for (repo, status) in &failed {
+        input_handler.write_line(&format!("    {} (exit {})", repo.name, status))?;
+    }
+    let interrupted = failed.iter().any(|&(_, status)| status == INTERRUPTED);
+    Ok(if interrupted { INTERRUPTED } else { 1 })
+}
+
+// Runs a line starting with `@` and returns its exit code, or `None` for any
+// other line. `@all` or `@<name>` without a command lists the repositories.
+pub fn handle_workspace_line<W: Write>(
+    line: &str,
+    config: &GitCmdConfig,
+    dry_run: bool,
+    input_handler: &mut InputHandler<W>,
+) -> io::Result<Option<i32>> {
+    let rest = match line.strip_prefix('@') {
+        Some(rest) => rest,
+        None => return Ok(None),
+    };
+    let (selector, command) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let command = command.trim();
+
+    input_handler.write_line("")?;
+    let repos = workspace_repos(&config.workspace);
+    if repos.is_empty() {
+        input_handler.write_line(
+            "No repositories found. List them in the `workspace { }` section of gitcmd.conf.",
+        )?;
+        return Ok(Some(1));
+    }
+    let selected = match select(&repos, selector) {
+        Ok(selected) => selected,
+        Err(e) => {
+            input_handler.write_line(&e)?;
+            return Ok(Some(1));
+        }
+    };
+
+    if command.is_empty() {
+        for repo in selected {
+            input_handler.write_line(&format!("{}  {}", repo.name, display_path(&repo.path)))?;
+        }
+        return Ok(Some(0));
+    }
+    run_in_repos(&selected, command, config, dry_run, input_handler).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo(name: &str, group: Option<&str>) -> Repo {
+        Repo {
+            name: name.to_string(),
+            group: group.map(str::to_string),
+            path: PathBuf::from("/src").join(name),
+        }
+    }
+
+    #[test]
+    fn repositories_are_selected_by_name_or_group() {
+        let repos = [
+            repo("api", Some("services")),
+            repo("worker", Some("services")),
+            repo("web", None),
+        ];
+        let names = |selector| -> Vec<String> {
+            select(&repos, selector)
+                .expect("selector should match")
+                .iter()
+                .map(|repo| repo.name.clone())
+                .collect()
+        };
+        assert_eq!(names("all"), ["api", "worker", "web"]);
+        assert_eq!(names("services"), ["api", "worker"]);
+        assert_eq!(names("web,api,services"), ["web", "api", "worker"]);
+        assert!(select(&repos, "mobile").is_err());
+    }
+}